@@ -0,0 +1,61 @@
+use crate::ci::CIResultStatus;
+
+const LABEL: &str = "CI";
+
+/// Approximate width of a character of 11px Verdana, which badges are rendered in.
+const CHARACTER_WIDTH: usize = 7;
+
+/// Horizontal padding on each side of the label and of the message.
+const PADDING: usize = 5;
+
+/// The message and colour a badge shows for the result of the latest build, if there is one.
+fn message(status: Option<&CIResultStatus>) -> (&'static str, &'static str) {
+    match status {
+        Some(CIResultStatus::Success) => ("passing", "#4c1"),
+        Some(CIResultStatus::Failure) => ("failing", "#e05d44"),
+        Some(CIResultStatus::Aborted) => ("cancelled", "#9f9f9f"),
+        Some(CIResultStatus::Errored) => ("errored", "#fe7d37"),
+        None => ("unknown", "#9f9f9f"),
+    }
+}
+
+/// Renders a flat SVG status badge, e.g. `CI | passing`, for READMEs to embed.
+pub fn render(status: Option<&CIResultStatus>) -> String {
+    let (message, colour) = message(status);
+    let label_width = LABEL.len() * CHARACTER_WIDTH + 2 * PADDING;
+    let message_width = message.len() * CHARACTER_WIDTH + 2 * PADDING;
+    let width = label_width + message_width;
+    let label_x = label_width / 2;
+    let message_x = label_width + message_width / 2;
+
+    format!(concat!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">"##,
+        r##"<title>{label}: {message}</title>"##,
+        r##"<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>"##,
+        r##"<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>"##,
+        r##"<g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{message_width}" height="20" fill="{colour}"/><rect width="{width}" height="20" fill="url(#s)"/></g>"##,
+        r##"<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">"##,
+        r##"<text x="{label_x}" y="14">{label}</text><text x="{message_x}" y="14">{message}</text></g>"##,
+        r##"</svg>"##,
+    ), width = width, label = LABEL, message = message, label_width = label_width, message_width = message_width, colour = colour, label_x = label_x, message_x = message_x)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::badge::render;
+    use crate::ci::CIResultStatus;
+
+    #[test]
+    fn will_render_the_status_of_the_latest_build() {
+        let badge = render(Some(&CIResultStatus::Failure));
+
+        assert!(badge.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="83" height="20""#));
+        assert!(badge.contains(r##"<rect x="24" width="59" height="20" fill="#e05d44"/>"##));
+        assert!(badge.contains(r#"<text x="12" y="14">CI</text><text x="53" y="14">failing</text>"#));
+    }
+
+    #[test]
+    fn will_render_unknown_without_a_build() {
+        assert!(render(None).contains("<title>CI: unknown</title>"));
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::ci::CIBuild;
+
+/// Identifies a patch revision across repositories.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RevisionKey {
+    pub rid: String,
+    pub patch_id: String,
+    pub revision_id: String,
+}
+
+/// A build a worker has taken on. It is registered before it is triggered, so that it can be
+/// cancelled, and is not started twice, while its pipeline is being set up.
+#[derive(Clone, Debug, Default)]
+struct RunningBuild {
+    /// The build in Concourse, once it has been triggered.
+    build: Option<CIBuild>,
+    /// Set when the build was cancelled before it was triggered.
+    cancelled: bool,
+}
+
+/// What has to be done to cancel the build of a revision.
+#[derive(Debug, PartialEq)]
+pub enum Cancellation {
+    /// The build is running and has to be aborted.
+    Abort(CIBuild),
+    /// The build has not been triggered yet. Its worker aborts it as soon as it is.
+    Pending,
+    NotRunning,
+}
+
+/// The builds currently running, shared between all workers so that one worker can act on a build
+/// another worker is watching, e.g. to cancel it. It also keeps the CI commands workers have taken
+/// on, as several jobs of a patch can see the same command before it is answered.
+#[derive(Clone, Default)]
+pub struct RunningBuilds {
+    builds: Arc<Mutex<HashMap<RevisionKey, RunningBuild>>>,
+    commands: Arc<Mutex<HashSet<String>>>,
+}
+
+impl RunningBuilds {
+    /// Registers the build of a revision that is about to be triggered. Returns false if the
+    /// revision is already being built, in which case it must not be built again.
+    pub fn start(&self, key: RevisionKey) -> bool {
+        match self.builds.lock().unwrap().entry(key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(RunningBuild::default());
+                true
+            }
+        }
+    }
+
+    /// Records the build triggered for a registered revision. Returns true if the build was
+    /// cancelled while it was being triggered, in which case it has to be aborted right away.
+    pub fn triggered(&self, key: &RevisionKey, build: CIBuild) -> bool {
+        let mut builds = self.builds.lock().unwrap();
+        let Some(running) = builds.get_mut(key) else {
+            return false;
+        };
+        running.build = Some(build);
+
+        running.cancelled
+    }
+
+    /// Takes on the command given in the comment `comment_id`. Returns false if a worker has taken
+    /// it on already. Commands are never released, as a job may still see a command unanswered in
+    /// a patch it loaded before the answer was posted.
+    pub fn claim_command(&self, comment_id: &str) -> bool {
+        self.commands.lock().unwrap().insert(comment_id.to_string())
+    }
+
+    pub fn finish(&self, key: &RevisionKey) {
+        self.builds.lock().unwrap().remove(key);
+    }
+
+    /// Returns true if the revision is being built, whether its build was triggered yet or not.
+    pub fn contains(&self, key: &RevisionKey) -> bool {
+        self.builds.lock().unwrap().contains_key(key)
+    }
+
+    pub fn get(&self, key: &RevisionKey) -> Option<CIBuild> {
+        self.builds.lock().unwrap().get(key)?.build.clone()
+    }
+
+    pub fn cancel(&self, key: &RevisionKey) -> Cancellation {
+        let mut builds = self.builds.lock().unwrap();
+        let Some(running) = builds.get_mut(key) else {
+            return Cancellation::NotRunning;
+        };
+
+        match &running.build {
+            Some(build) => Cancellation::Abort(build.clone()),
+            None => {
+                running.cancelled = true;
+                Cancellation::Pending
+            }
+        }
+    }
+
+    /// Returns the revisions of a patch that are being built.
+    pub fn of_patch(&self, rid: &str, patch_id: &str) -> Vec<RevisionKey> {
+        self.builds.lock().unwrap()
+            .keys()
+            .filter(|key| key.rid == rid && key.patch_id == patch_id)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builds::{Cancellation, RevisionKey, RunningBuilds};
+    use crate::ci::CIBuild;
+
+    fn key(revision_id: &str) -> RevisionKey {
+        RevisionKey {
+            rid: String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5"),
+            patch_id: String::from("f0b0c8a4d3e2"),
+            revision_id: String::from(revision_id),
+        }
+    }
+
+    #[test]
+    fn will_track_running_builds_across_clones() {
+        let builds = RunningBuilds::default();
+        let other = builds.clone();
+        let build = CIBuild { id: 3094, url: String::from("http://localhost:8080/builds/3094") };
+
+        assert!(builds.start(key("a1")));
+        assert!(!other.start(key("a1")));
+        assert!(other.contains(&key("a1")));
+        assert_eq!(other.get(&key("a1")), None);
+
+        assert!(!builds.triggered(&key("a1"), build.clone()));
+        assert_eq!(other.get(&key("a1")), Some(build));
+        assert!(!other.contains(&key("b2")));
+
+        other.finish(&key("a1"));
+
+        assert!(!builds.contains(&key("a1")));
+    }
+
+    #[test]
+    fn will_cancel_builds_that_are_not_triggered_yet() {
+        let builds = RunningBuilds::default();
+        let build = CIBuild { id: 3094, url: String::from("http://localhost:8080/builds/3094") };
+
+        builds.start(key("a1"));
+
+        assert_eq!(builds.cancel(&key("a1")), Cancellation::Pending);
+        assert!(builds.triggered(&key("a1"), build.clone()));
+        assert_eq!(builds.cancel(&key("a1")), Cancellation::Abort(build.clone()));
+        assert_eq!(builds.cancel(&key("b2")), Cancellation::NotRunning);
+        assert!(!builds.triggered(&key("b2"), build));
+        assert!(!builds.contains(&key("b2")));
+    }
+
+    #[test]
+    fn will_claim_each_command_once() {
+        let builds = RunningBuilds::default();
+        let other = builds.clone();
+
+        assert!(builds.claim_command("c7d3a9"));
+        assert!(!other.claim_command("c7d3a9"));
+        assert!(other.claim_command("e1f2b4"));
+    }
+
+    #[test]
+    fn will_find_running_builds_of_a_patch() {
+        let builds = RunningBuilds::default();
+
+        builds.start(key("a1"));
+
+        assert_eq!(builds.of_patch("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "f0b0c8a4d3e2"), vec![key("a1")]);
+        assert!(builds.of_patch("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "b7c1d2").is_empty());
+    }
+}
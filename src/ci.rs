@@ -1,6 +1,8 @@
 use std::fmt::{Display, Formatter};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct RadicleApiUrl(pub String);
@@ -35,7 +37,7 @@ impl Display for PipelineName {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct JobName(pub String);
 
 impl Display for JobName {
@@ -53,31 +55,54 @@ impl Display for BuildName {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CIResultStatus {
     Success,
     Failure,
+    Aborted,
+    /// The build could not run to completion, e.g. because a resource could not be fetched.
+    Errored,
 }
 
-#[derive(Debug)]
+impl CIResultStatus {
+    /// The name the status is serialized with.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CIResultStatus::Success => "success",
+            CIResultStatus::Failure => "failure",
+            CIResultStatus::Aborted => "aborted",
+            CIResultStatus::Errored => "errored",
+        }
+    }
+}
+
+/// The outcome of a single job of the pipeline.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct JobResult {
+    pub name: JobName,
+    pub status: CIResultStatus,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct CIResult {
     pub status: CIResultStatus,
     pub url: String,
+    /// The outcome of every job of the pipeline that has run.
+    pub jobs: Vec<JobResult>,
+    /// How long the build ran for, in seconds, if known.
+    pub duration: Option<u64>,
 }
 
 impl CIResult {
-    pub fn has_completed_successfully(&self) -> bool {
-        self.status == CIResultStatus::Success
+    /// The result of a build that could not run to completion, e.g. because its pipeline could not
+    /// be set up. The URL is empty if the build was never triggered.
+    pub fn errored(url: String) -> Self {
+        CIResult { status: CIResultStatus::Errored, url, jobs: Vec::new(), duration: None }
     }
 
-    pub fn get_report_message(&self) -> String {
-        let status = if self.has_completed_successfully() {
-            "The CI job has PASSED! 🎉"
-        } else {
-            "The CI job has FAILED! 🙁"
-        };
-
-        format!("{}\n\nPlease visit {} for more details.", status, self.url)
+    pub fn has_completed_successfully(&self) -> bool {
+        self.status == CIResultStatus::Success
     }
 }
 
@@ -87,13 +112,39 @@ type ProjectId = String;
 
 #[derive(Clone, Debug)]
 pub struct CIJob {
-    pub patch_revision_id: PatchRevisionId,
+    /// Set for patch builds, with the revision built.
+    pub patch_revision_id: Option<PatchRevisionId>,
     pub patch_head: PatchHead,
     pub project_id: ProjectId,
     pub pipeline_config: PipelineConfig,
+    /// Set for patch builds, which run in a pipeline per patch.
+    pub patch_id: Option<String>,
+    /// Set for branch builds, which run in a pipeline per branch.
+    pub branch: Option<String>,
+    /// Set for release builds, which run in a pipeline of their own.
+    pub tag_name: Option<String>,
+}
+
+/// A build that has been triggered and may still be running.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CIBuild {
+    pub id: usize,
+    pub url: String,
 }
 
 pub trait CI: Clone {
-    fn setup(&mut self, job: CIJob) -> Result<PipelineName, anyhow::Error>;
-    fn run_pipeline(&mut self, pipeline_name: &PipelineName) -> Result<CIResult, anyhow::Error>;
+    fn setup(&mut self, job: &CIJob) -> Result<PipelineName, Error>;
+    fn trigger_pipeline(&mut self, job: &CIJob, pipeline_name: &PipelineName) -> Result<CIBuild, Error>;
+    /// Triggers a single, named job of the pipeline instead of the first one.
+    fn trigger_pipeline_job(&mut self, job: &CIJob, pipeline_name: &PipelineName, job_name: &JobName) -> Result<CIBuild, Error>;
+    /// Blocks until the build has completed.
+    fn watch_build(&mut self, build: &CIBuild) -> Result<CIResult, Error>;
+    fn abort_build(&mut self, build: &CIBuild) -> Result<(), Error>;
+    /// Removes the pipeline of a patch that will not be built again, e.g. because it was merged.
+    fn destroy_patch_pipeline(&mut self, project_id: &str, patch_id: &str) -> Result<(), Error>;
+
+    fn run_pipeline(&mut self, job: &CIJob, pipeline_name: &PipelineName) -> Result<CIResult, Error> {
+        let build = self.trigger_pipeline(job, pipeline_name)?;
+        self.watch_build(&build)
+    }
 }
@@ -0,0 +1,256 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use radicle::cob;
+use radicle::cob::store;
+use radicle::cob::{ObjectId, TypeName};
+use radicle::crypto::{PublicKey, Signer};
+use radicle::prelude::ReadRepository;
+use radicle::storage::git::Repository;
+use serde::{Deserialize, Serialize};
+
+use crate::ci::{CIResult, CIResultStatus, JobResult};
+
+/// The type name of CI job run collaborative objects.
+pub const TYPE_NAME: &str = "xyz.radicle.ci.job";
+
+pub fn type_name() -> &'static TypeName {
+    static TYPENAME: OnceLock<TypeName> = OnceLock::new();
+    TYPENAME.get_or_init(|| TypeName::from_str(TYPE_NAME).expect("type name is valid"))
+}
+
+pub type Op = cob::Op<Action>;
+
+/// The state of a CI job run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Aborted,
+    Errored,
+}
+
+impl From<&CIResultStatus> for RunStatus {
+    fn from(status: &CIResultStatus) -> Self {
+        match status {
+            CIResultStatus::Success => RunStatus::Succeeded,
+            CIResultStatus::Failure => RunStatus::Failed,
+            CIResultStatus::Aborted => RunStatus::Aborted,
+            CIResultStatus::Errored => RunStatus::Errored,
+        }
+    }
+}
+
+/// A status the run went through, and when, in seconds since the Unix epoch.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Transition {
+    pub status: RunStatus,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Action {
+    #[serde(rename_all = "camelCase")]
+    Create {
+        /// The id of the run in the CI system, e.g. the Concourse build id.
+        run_id: usize,
+        /// The patch revision built, if the run was triggered by a patch.
+        revision: Option<String>,
+        commit: String,
+        log_url: String,
+        timestamp: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Finish {
+        status: RunStatus,
+        jobs: Vec<JobResult>,
+        log_url: String,
+        timestamp: u64,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("job run was updated before it was created")]
+    NotCreated,
+}
+
+/// A run of a CI pipeline, signed by the broker and replicated with the repository so that tools
+/// can show the CI status of a revision or commit.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JobRun {
+    /// The broker that recorded the run. Anyone can create job runs, only the broker's are trusted.
+    pub author: Option<PublicKey>,
+    pub run_id: usize,
+    pub revision: Option<String>,
+    pub commit: String,
+    pub log_url: String,
+    pub transitions: Vec<Transition>,
+    pub jobs: Vec<JobResult>,
+}
+
+impl JobRun {
+    pub fn status(&self) -> Option<RunStatus> {
+        self.transitions.last().map(|transition| transition.status)
+    }
+
+    fn action(&mut self, action: Action) -> Result<(), Error> {
+        match action {
+            Action::Create { run_id, revision, commit, log_url, timestamp } => {
+                *self = JobRun {
+                    author: None,
+                    run_id,
+                    revision,
+                    commit,
+                    log_url,
+                    transitions: vec![Transition { status: RunStatus::Running, timestamp }],
+                    jobs: Vec::new(),
+                };
+            }
+            Action::Finish { status, jobs, log_url, timestamp } => {
+                if self.transitions.is_empty() {
+                    return Err(Error::NotCreated);
+                }
+                self.transitions.push(Transition { status, timestamp });
+                self.jobs = jobs;
+                self.log_url = log_url;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl store::FromHistory for JobRun {
+    type Action = Action;
+    type Error = Error;
+
+    fn type_name() -> &'static TypeName {
+        type_name()
+    }
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        if self.transitions.is_empty() {
+            return Err(Error::NotCreated);
+        }
+        Ok(())
+    }
+
+    fn apply<R: ReadRepository>(&mut self, ops: impl IntoIterator<Item=Op>, _repo: &R) -> Result<(), Self::Error> {
+        for op in ops {
+            for action in op.actions {
+                let created = matches!(action, Action::Create { .. });
+                self.action(action)?;
+                if created {
+                    self.author = Some(op.author);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The job runs of a repository.
+pub struct JobRuns<'a> {
+    raw: store::Store<'a, JobRun>,
+}
+
+impl<'a> JobRuns<'a> {
+    pub fn open(repository: &'a Repository) -> Result<Self, store::Error> {
+        Ok(Self { raw: store::Store::open(repository)? })
+    }
+
+    pub fn create<G: Signer>(&mut self, run_id: usize, revision: Option<String>, commit: String, log_url: String, timestamp: u64, signer: &G) -> Result<ObjectId, store::Error> {
+        let action = Action::Create { run_id, revision, commit, log_url, timestamp };
+        let (id, _) = self.raw.create("Create job run", action, signer)?;
+
+        Ok(id)
+    }
+
+    /// Returns true if `author` recorded a run of the patch revision.
+    pub fn has_run(&self, revision: &str, author: &PublicKey) -> Result<bool, store::Error> {
+        for run in self.raw.all()? {
+            let (_, run) = run?;
+            if run.revision.as_deref() == Some(revision) && run.author.as_ref() == Some(author) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn finish<G: Signer>(&mut self, id: &ObjectId, result: &CIResult, timestamp: u64, signer: &G) -> Result<(), store::Error> {
+        let action = Action::Finish {
+            status: RunStatus::from(&result.status),
+            jobs: result.jobs.clone(),
+            log_url: result.url.clone(),
+            timestamp,
+        };
+        self.raw.update(type_name(), *id, "Finish job run", action, signer)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ci::{CIResultStatus, JobName, JobResult};
+    use crate::cob::{Action, Error, JobRun, RunStatus, Transition};
+
+    fn create() -> Action {
+        Action::Create {
+            run_id: 3094,
+            revision: Some(String::from("a1b2c3")),
+            commit: String::from("d4e5f6"),
+            log_url: String::from("http://localhost:8080/builds/3094"),
+            timestamp: 1690735633,
+        }
+    }
+
+    #[test]
+    fn will_record_status_transitions_and_jobs() {
+        let mut run = JobRun::default();
+        let jobs = vec![JobResult { name: JobName(String::from("test")), status: CIResultStatus::Failure }];
+
+        run.action(create()).unwrap();
+        run.action(Action::Finish {
+            status: RunStatus::Failed,
+            jobs: jobs.clone(),
+            log_url: String::from("http://localhost:8080/builds/3094"),
+            timestamp: 1690735639,
+        }).unwrap();
+
+        assert_eq!(run.run_id, 3094);
+        assert_eq!(run.revision.as_deref(), Some("a1b2c3"));
+        assert_eq!(run.status(), Some(RunStatus::Failed));
+        assert_eq!(run.transitions, vec![
+            Transition { status: RunStatus::Running, timestamp: 1690735633 },
+            Transition { status: RunStatus::Failed, timestamp: 1690735639 },
+        ]);
+        assert_eq!(run.jobs, jobs);
+    }
+
+    #[test]
+    fn will_not_finish_a_run_that_was_not_created() {
+        let mut run = JobRun::default();
+        let result = run.action(Action::Finish {
+            status: RunStatus::Succeeded,
+            jobs: Vec::new(),
+            log_url: String::new(),
+            timestamp: 1690735639,
+        });
+
+        assert!(matches!(result, Err(Error::NotCreated)));
+    }
+
+    #[test]
+    fn will_serialize_actions_in_camel_case() -> Result<(), serde_json::Error> {
+        let json = serde_json::to_value(create())?;
+
+        assert_eq!(json["type"], "create");
+        assert_eq!(json["runId"], 3094);
+        assert_eq!(json["logUrl"], "http://localhost:8080/builds/3094");
+        Ok(())
+    }
+}
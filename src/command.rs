@@ -0,0 +1,94 @@
+use std::fmt::{Display, Formatter};
+
+use crate::ci::JobName;
+
+/// The prefix patch comments must start with to be considered a command for the broker.
+pub const COMMAND_PREFIX: &str = "/ci";
+
+/// A command given to the broker through a patch comment, e.g. `/ci rerun`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CICommand {
+    /// Builds the revision the comment was posted on again.
+    Rerun,
+    /// Aborts the running build of the revision the comment was posted on.
+    Cancel,
+    /// Triggers a single job of the pipeline, e.g. one that is too expensive to run on every
+    /// revision.
+    Run(JobName),
+    Unknown(String),
+}
+
+impl CICommand {
+    /// Parses the first non-empty line of a comment. Returns `None` if the comment is not addressed
+    /// to the broker at all.
+    pub fn parse(body: &str) -> Option<CICommand> {
+        let line = body.lines().map(str::trim).find(|line| !line.is_empty())?;
+        let mut words = line.split_whitespace();
+
+        if words.next()? != COMMAND_PREFIX {
+            return None;
+        }
+
+        Some(match words.next() {
+            Some("rerun") => CICommand::Rerun,
+            Some("cancel") => CICommand::Cancel,
+            Some("run") => match words.next() {
+                Some(job_name) => CICommand::Run(JobName(job_name.to_string())),
+                None => CICommand::Unknown(String::from("run")),
+            },
+            Some(other) => CICommand::Unknown(other.to_string()),
+            None => CICommand::Unknown(String::new()),
+        })
+    }
+}
+
+impl Display for CICommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CICommand::Rerun => write!(f, "{COMMAND_PREFIX} rerun"),
+            CICommand::Cancel => write!(f, "{COMMAND_PREFIX} cancel"),
+            CICommand::Run(job_name) => write!(f, "{COMMAND_PREFIX} run {job_name}"),
+            CICommand::Unknown(command) => write!(f, "{COMMAND_PREFIX} {command}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ci::JobName;
+    use crate::command::CICommand;
+
+    #[test]
+    fn will_parse_rerun_and_cancel_commands() {
+        assert_eq!(CICommand::parse("/ci rerun"), Some(CICommand::Rerun));
+        assert_eq!(CICommand::parse("  /ci   cancel  "), Some(CICommand::Cancel));
+        assert_eq!(CICommand::parse("\n/ci rerun\nThe last build was flaky."), Some(CICommand::Rerun));
+    }
+
+    #[test]
+    fn will_parse_run_command_with_job_name() {
+        let command = CICommand::parse("/ci run benchmarks");
+
+        assert_eq!(command, Some(CICommand::Run(JobName(String::from("benchmarks")))));
+        assert_eq!(command.unwrap().to_string(), "/ci run benchmarks");
+    }
+
+    #[test]
+    fn will_parse_run_command_without_job_name_as_unknown() {
+        assert_eq!(CICommand::parse("/ci run"), Some(CICommand::Unknown(String::from("run"))));
+    }
+
+    #[test]
+    fn will_parse_unknown_commands() {
+        assert_eq!(CICommand::parse("/ci deploy"), Some(CICommand::Unknown(String::from("deploy"))));
+        assert_eq!(CICommand::parse("/ci"), Some(CICommand::Unknown(String::new())));
+    }
+
+    #[test]
+    fn will_ignore_comments_that_are_not_commands() {
+        assert_eq!(CICommand::parse("LGTM"), None);
+        assert_eq!(CICommand::parse("Could you /ci rerun this?"), None);
+        assert_eq!(CICommand::parse("/cicd rerun"), None);
+        assert_eq!(CICommand::parse(""), None);
+    }
+}
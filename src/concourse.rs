@@ -1,9 +1,13 @@
 mod pipeline_configuration;
 mod pipeline_job;
 mod token;
+mod user;
 
 pub mod api;
+pub mod auth;
 pub mod ci;
+pub mod credentials;
+pub mod error;
 pub mod response_error;
 mod build;
 mod pipeline;
@@ -1,22 +1,38 @@
-use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use hyper::{Body, Client, Request, Response};
 use hyper::body::Buf;
 use hyper::client::HttpConnector;
-use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
 use hyper_tls::HttpsConnector;
 use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
 
 use crate::ci::{BuildName, JobName, PipelineConfig, PipelineName};
+use crate::concourse::auth::Authentication;
 use crate::concourse::build::{Build, BuildID};
-use crate::concourse::ci::ConcourseUrl;
+use crate::concourse::ci::{ConcourseTeam, ConcourseUrl};
+use crate::concourse::error::{ConcourseError, error_from_response};
 use crate::concourse::pipeline::Pipeline;
 use crate::concourse::pipeline_configuration::PipelineConfiguration;
 use crate::concourse::pipeline_job::PipelineJob;
-use crate::concourse::response_error::ResponseError;
 use crate::concourse::token::Token;
+use crate::concourse::user::UserInfo;
+use crate::metrics::metrics;
 
-pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+pub type Result<T> = std::result::Result<T, ConcourseError>;
+
+/// Tokens are renewed once they are this close to expiring, so that requests issued right before
+/// the expiry do not fail.
+const TOKEN_RENEWAL_MARGIN: Duration = Duration::from_secs(60);
+
+/// The credentials of the fly client, which this broker impersonates for password grants. The value
+/// is always the same and was found in the Concourse repository.
+const FLY_CLIENT_CREDENTIALS: &str = "Basic Zmx5OlpteDU=";
+
+const TOKEN_SCOPE: &str = "openid profile email federated:id groups offline_access";
 
 
 async fn deserialize_json_response<T>(response: Response<Body>) -> Result<T>
@@ -28,64 +44,60 @@ async fn deserialize_json_response<T>(response: Response<Body>) -> Result<T>
     Ok(result)
 }
 
-async fn deserialize_string_response(response: Response<Body>) -> Result<String> {
-    let content_length = response.headers().get(CONTENT_LENGTH).unwrap().to_str()?.parse::<usize>().unwrap();
-    let body = hyper::body::aggregate(response).await?;
-    let mut dst = vec![0; content_length];
-    let num = body.reader().read(&mut dst)?;
-    let result = std::str::from_utf8(&dst[..num])?;
-    Ok(result.to_string())
-}
-
 #[derive(Clone)]
 pub struct ConcourseAPI {
     client: Client<HttpsConnector<HttpConnector>>,
-    ci_pass: String,
-    ci_user: String,
+    auth: Authentication,
     concourse_uri: ConcourseUrl,
-    token: Option<Token>,
+    /// Shared between all clones, so that every worker reuses the same token.
+    token: Arc<Mutex<Option<Token>>>,
 }
 
 impl ConcourseAPI {
-    pub fn new(concourse_uri: ConcourseUrl, ci_user: String, ci_pass: String) -> ConcourseAPI {
+    pub fn new(concourse_uri: ConcourseUrl, auth: Authentication) -> ConcourseAPI {
         let https = HttpsConnector::new();
         let client = Client::builder().build::<_, hyper::Body>(https);
         ConcourseAPI {
             client,
             concourse_uri,
-            ci_user,
-            ci_pass,
-            token: None,
+            auth,
+            token: Arc::new(Mutex::new(None)),
         }
     }
 
     /// Obtain credentials. This is how we get the access token required for all other API requests.
-    /// Notice the Authorization header. Its value is always the same and was found in the Concourse
-    /// repository.
+    /// It always obtains a new token; prefer letting the API methods acquire a token on demand,
+    /// which reuses and renews the cached one.
     pub async fn get_access_token(&mut self) -> Result<Token> {
-        let path = "/sky/issuer/token";
+        let token = self.request_new_token().await?;
+        *self.token.lock().await = Some(token.clone());
 
-        let request = Request::builder()
-            .method("POST")
-            .uri(format!("{}{}", self.concourse_uri, path))
-            .header(AUTHORIZATION, "Basic Zmx5OlpteDU=")
-            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
-            .body(format!("grant_type=password&username={}&password={}&scope=openid%20profile%20email%20federated:id%20groups", self.ci_user, self.ci_pass).into())?;
+        Ok(token)
+    }
 
-        let response = self.client.request(request).await?;
-        let token = deserialize_json_response::<Token>(response).await?;
+    /// Returns information about the authenticated user, including the teams they belong to.
+    pub async fn get_user_info(&mut self) -> Result<UserInfo> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
-        self.token = Some(token.clone());
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("{}/api/v1/user", self.concourse_uri))
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .body("".into())?;
 
-        Ok(token)
+        let response = self.send("get_user_info", request).await?;
+        let status = response.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(error_from_response(response).await)
+        } else {
+            deserialize_json_response::<UserInfo>(response).await
+        }
     }
 
     /// Returns a list of all pipelines.
     pub async fn get_all_pipelines(&mut self) -> Result<Vec<Pipeline>> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("GET")
@@ -93,12 +105,11 @@ impl ConcourseAPI {
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_all_pipelines", request).await?;
         let status = response.status();
 
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             let result = deserialize_json_response::<Vec<Pipeline>>(response).await?;
             Ok(result)
@@ -107,10 +118,7 @@ impl ConcourseAPI {
 
     /// Returns a list of all pipeline jobs.
     pub async fn get_all_jobs(&mut self) -> Result<Vec<PipelineJob>> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("GET")
@@ -119,12 +127,11 @@ impl ConcourseAPI {
             .body("".into())?;
 
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_all_jobs", request).await?;
         let status = response.status();
 
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             let result = deserialize_json_response::<Vec<PipelineJob>>(response).await?;
             Ok(result)
@@ -133,10 +140,7 @@ impl ConcourseAPI {
 
     /// Returns a specific pipeline job build.
     pub async fn get_build(&mut self, build_id: &BuildID) -> Result<Build> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request: Request<Body> = Request::builder()
             .method("GET")
@@ -144,61 +148,49 @@ impl ConcourseAPI {
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_build", request).await?;
         let status = response.status();
 
-        // Both 4xx and 5xx responses return a string body that cannot be parsed as JSON.
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             deserialize_json_response::<Build>(response).await
         }
     }
 
-    pub async fn get_pipeline(&mut self, pipeline_name: &PipelineName) -> Result<Pipeline> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn get_pipeline(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName) -> Result<Pipeline> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("GET")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}", self.concourse_uri, pipeline_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}", self.concourse_uri, team, pipeline_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_pipeline", request).await?;
         let status = response.status();
 
-        // Both 4xx and 5xx responses return a string body that cannot be parsed as JSON.
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             deserialize_json_response::<Pipeline>(response).await
         }
     }
 
-    pub async fn get_pipeline_config(&mut self, pipeline_name: &PipelineName) -> Result<PipelineConfiguration> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn get_pipeline_config(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName) -> Result<PipelineConfiguration> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("GET")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/config", self.concourse_uri, pipeline_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/config", self.concourse_uri, team, pipeline_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_pipeline_config", request).await?;
         let status = response.status();
 
-        // Both 4xx and 5xx responses return a string body that cannot be parsed as JSON.
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             let config_version = response
                 .headers()
@@ -215,11 +207,8 @@ impl ConcourseAPI {
     }
 
     /// Create a new pipeline in concourse based on the configuration provided.
-    pub async fn create_pipeline_config(&mut self, pipeline_name: &PipelineName, config: PipelineConfig, version: Option<String>) -> Result<()> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn create_pipeline_config(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName, config: PipelineConfig, version: Option<String>) -> Result<()> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let config_version = match version {
             None => String::from("1"),
@@ -228,19 +217,17 @@ impl ConcourseAPI {
 
         let request = Request::builder()
             .method("PUT")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/config", self.concourse_uri, pipeline_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/config", self.concourse_uri, team, pipeline_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .header(CONTENT_TYPE, "application/x-yaml")
             .header("X-Concourse-Config-Version", config_version)
             .body(config.0.into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("create_pipeline_config", request).await?;
         let status = response.status();
 
-        // Both 4xx and 5xx responses return a string body that cannot be parsed as JSON.
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             Ok(())
         }
@@ -248,50 +235,100 @@ impl ConcourseAPI {
 
     /// After the pipeline is created it is in a paused state. This method will unpause it making it
     /// available for execution.
-    pub async fn unpause_pipeline(&mut self, pipeline_name: &PipelineName) -> Result<()> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn unpause_pipeline(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName) -> Result<()> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
+
+        let request = Request::builder()
+            .method("PUT")
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/unpause", self.concourse_uri, team, pipeline_name))
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .body("".into())?;
+
+        let response = self.send("unpause_pipeline", request).await?;
+        let status = response.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(error_from_response(response).await)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Pauses a pipeline. Running builds are not affected, but no new builds will be scheduled.
+    pub async fn pause_pipeline(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName) -> Result<()> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("PUT")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/unpause", self.concourse_uri, pipeline_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/pause", self.concourse_uri, team, pipeline_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("pause_pipeline", request).await?;
         let status = response.status();
 
-        // Both 4xx and 5xx responses return a string body that cannot be parsed as JSON.
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             Ok(())
         }
     }
 
+    /// Archives a pipeline. It is paused and hidden, but its build history is kept.
+    pub async fn archive_pipeline(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName) -> Result<()> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
+
+        let request = Request::builder()
+            .method("PUT")
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/archive", self.concourse_uri, team, pipeline_name))
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .body("".into())?;
+
+        let response = self.send("archive_pipeline", request).await?;
+        let status = response.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(error_from_response(response).await)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Destroys a pipeline together with its build history.
+    pub async fn destroy_pipeline(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName) -> Result<()> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
+
+        let request = Request::builder()
+            .method("DELETE")
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}", self.concourse_uri, team, pipeline_name))
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .body("".into())?;
+
+        let response = self.send("destroy_pipeline", request).await?;
+        let status = response.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(error_from_response(response).await)
+        } else {
+            Ok(())
+        }
+    }
 
     /// Get all pipeline jobs.
-    pub async fn get_all_pipeline_jobs(&mut self, pipeline_name: &PipelineName) -> Result<Vec<PipelineJob>> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn get_all_pipeline_jobs(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName) -> Result<Vec<PipelineJob>> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("GET")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/jobs", self.concourse_uri, pipeline_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/jobs", self.concourse_uri, team, pipeline_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_all_pipeline_jobs", request).await?;
         let status = response.status();
 
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             let result = deserialize_json_response::<Vec<PipelineJob>>(response).await?;
             Ok(result)
@@ -299,49 +336,40 @@ impl ConcourseAPI {
     }
 
     /// Trigger a job belonging to a specific pipeline.
-    pub async fn trigger_pipeline_job(&mut self, pipeline_name: &PipelineName, job_name: &JobName) -> Result<Build> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn trigger_pipeline_job(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName, job_name: &JobName) -> Result<Build> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request: Request<Body> = Request::builder()
             .method("POST")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/jobs/{}/builds", self.concourse_uri, pipeline_name, job_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/jobs/{}/builds", self.concourse_uri, team, pipeline_name, job_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("trigger_pipeline_job", request).await?;
         let status = response.status();
 
-        // Both 4xx and 5xx responses return a string body that cannot be parsed as JSON.
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             deserialize_json_response::<Build>(response).await
         }
     }
 
     /// Trigger a new build for a specific pipeline job
-    pub async fn trigger_new_pipeline_job_build(&mut self, pipeline_name: &PipelineName, job_name: &JobName) -> Result<Build> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn trigger_new_pipeline_job_build(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName, job_name: &JobName) -> Result<Build> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("POST")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/jobs/{}/builds", self.concourse_uri, pipeline_name, job_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/jobs/{}/builds", self.concourse_uri, team, pipeline_name, job_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("trigger_new_pipeline_job_build", request).await?;
         let status = response.status();
 
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             let result = deserialize_json_response::<Build>(response).await?;
             Ok(result)
@@ -350,58 +378,277 @@ impl ConcourseAPI {
 
 
     /// Returns data for a specific pipeline job build.
-    pub async fn get_pipeline_job_build(&mut self, pipeline_name: &PipelineName, job_name: &JobName, build_name: &BuildName) -> Result<Build> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn get_pipeline_job_build(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName, job_name: &JobName, build_name: &BuildName) -> Result<Build> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("GET")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/jobs/{}/builds/{}", self.concourse_uri, pipeline_name, job_name, build_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/jobs/{}/builds/{}", self.concourse_uri, team, pipeline_name, job_name, build_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_pipeline_job_build", request).await?;
         let status = response.status();
 
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             let result = deserialize_json_response::<Build>(response).await?;
             Ok(result)
         }
     }
 
+    /// Aborts a pending or running build.
+    pub async fn abort_build(&mut self, build_id: &BuildID) -> Result<()> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
+
+        let request = Request::builder()
+            .method("PUT")
+            .uri(format!("{}/api/v1/builds/{}/abort", self.concourse_uri, build_id))
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .body("".into())?;
+
+        let response = self.send("abort_build", request).await?;
+        let status = response.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(error_from_response(response).await)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reruns a finished pipeline job build with the same inputs. The new build is named after the
+    /// original one, e.g. `4.1` for the first rerun of build `4`.
+    pub async fn rerun_pipeline_job_build(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName, job_name: &JobName, build_name: &BuildName) -> Result<Build> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
+
+        let request = Request::builder()
+            .method("POST")
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/jobs/{}/builds/{}", self.concourse_uri, team, pipeline_name, job_name, build_name))
+            .header(AUTHORIZATION, format!("Bearer {access_token}"))
+            .body("".into())?;
+
+        let response = self.send("rerun_pipeline_job_build", request).await?;
+        let status = response.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(error_from_response(response).await)
+        } else {
+            deserialize_json_response::<Build>(response).await
+        }
+    }
+
     /// Returns a list of all builds in concourse related to a specific pipeline job.
-    pub async fn get_all_pipeline_job_builds(&mut self, pipeline_name: &PipelineName, job_name: &JobName) -> Result<Vec<Build>> {
-        let access_token = match self.acquire_access_token().await {
-            Ok(token) => token.get_access_token()?,
-            Err(_) => return Err(Box::new(ResponseError { errors: vec!["No access token acquired yet.".into()], warnings: None })),
-        };
+    pub async fn get_all_pipeline_job_builds(&mut self, team: &ConcourseTeam, pipeline_name: &PipelineName, job_name: &JobName) -> Result<Vec<Build>> {
+        let access_token = self.acquire_access_token().await?.get_access_token()?;
 
         let request = Request::builder()
             .method("GET")
-            .uri(format!("{}/api/v1/teams/main/pipelines/{}/jobs/{}/builds", self.concourse_uri, pipeline_name, job_name))
+            .uri(format!("{}/api/v1/teams/{}/pipelines/{}/jobs/{}/builds", self.concourse_uri, team, pipeline_name, job_name))
             .header(AUTHORIZATION, format!("Bearer {access_token}"))
             .body("".into())?;
 
-        let response = self.client.request(request).await?;
+        let response = self.send("get_all_pipeline_job_builds", request).await?;
         let status = response.status();
 
         if status.is_client_error() || status.is_server_error() {
-            let string = deserialize_string_response(response).await?;
-            Err(Box::new(ResponseError { errors: vec![string], warnings: None }))
+            Err(error_from_response(response).await)
         } else {
             let result = deserialize_json_response::<Vec<Build>>(response).await?;
             Ok(result)
         }
     }
 
+    /// Sends a request to the Concourse API, recording its latency and whether it failed under
+    /// `endpoint`.
+    async fn send(&self, endpoint: &'static str, request: Request<Body>) -> Result<Response<Body>> {
+        let started = Instant::now();
+        let response = self.client.request(request).await;
+        let latency = started.elapsed();
+        let failed = match &response {
+            Ok(response) => response.status().is_client_error() || response.status().is_server_error(),
+            Err(_) => true,
+        };
+        debug!(endpoint, status = response.as_ref().ok().map(|response| response.status().as_u16()), ?latency, "Concourse API request");
+        metrics().api_request(endpoint, latency, failed);
+
+        Ok(response?)
+    }
+
+    /// Returns the cached access token, renewing it when it is about to expire. The refresh token is
+    /// used when available so that the password or client secret is only sent when there is no
+    /// other way. Pre-issued bearer tokens are read from their source every time instead.
     async fn acquire_access_token(&mut self) -> Result<Token> {
-        if self.token.is_none() || self.token.as_ref().is_some_and(|token| token.has_expired()) {
-            self.get_access_token().await
-        } else { Ok(self.token.clone().unwrap()) }
+        if let Authentication::Bearer { token } = &self.auth {
+            return Ok(Token::pre_issued(token.secret().map_err(ConcourseError::credentials)?));
+        }
+
+        let cache = self.token.clone();
+        let mut cached = cache.lock().await;
+
+        if let Some(token) = cached.as_ref() {
+            if !token.expires_within(TOKEN_RENEWAL_MARGIN) {
+                return Ok(token.clone());
+            }
+
+            match token.get_refresh_token() {
+                Some(Ok(refresh_token)) => match self.request_refreshed_token(&refresh_token).await {
+                    Ok(mut renewed) => {
+                        if renewed.refresh_token.is_none() {
+                            renewed.refresh_token = token.refresh_token.clone();
+                        }
+                        *cached = Some(renewed.clone());
+                        metrics().token_refreshed("refresh_token");
+                        return Ok(renewed);
+                    }
+                    Err(error) => warn!(%error, "Failed to refresh access token, requesting a new one"),
+                },
+                Some(Err(error)) => warn!(%error, "Refresh token is not valid UTF-8, requesting a new access token"),
+                None => (),
+            }
+        }
+
+        let renewal = cached.is_some();
+        let token = self.request_new_token().await?;
+        *cached = Some(token.clone());
+        if renewal {
+            metrics().token_refreshed("new_token");
+        }
+
+        Ok(token)
+    }
+
+    async fn request_new_token(&self) -> Result<Token> {
+        match &self.auth {
+            Authentication::Password { user, password } => {
+                let password = password.secret().map_err(ConcourseError::credentials)?;
+                let body = form_urlencoded::Serializer::new(String::new())
+                    .append_pair("grant_type", "password")
+                    .append_pair("username", user)
+                    .append_pair("password", std::str::from_utf8(password.unsecure()).map_err(ConcourseError::credentials)?)
+                    .append_pair("scope", TOKEN_SCOPE)
+                    .finish();
+                self.request_token(body).await
+            }
+            Authentication::ClientCredentials { client_id, client_secret } => {
+                let client_secret = client_secret.secret().map_err(ConcourseError::credentials)?;
+                let body = form_urlencoded::Serializer::new(String::new())
+                    .append_pair("grant_type", "client_credentials")
+                    .append_pair("client_id", client_id)
+                    .append_pair("client_secret", std::str::from_utf8(client_secret.unsecure()).map_err(ConcourseError::credentials)?)
+                    .append_pair("scope", TOKEN_SCOPE)
+                    .finish();
+                self.request_token(body).await
+            }
+            Authentication::Bearer { token } => Ok(Token::pre_issued(token.secret().map_err(ConcourseError::credentials)?)),
+        }
+    }
+
+    async fn request_refreshed_token(&self, refresh_token: &str) -> Result<Token> {
+        let body = form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "refresh_token")
+            .append_pair("refresh_token", refresh_token)
+            .append_pair("scope", TOKEN_SCOPE)
+            .finish();
+        self.request_token(body).await
+    }
+
+    async fn request_token(&self, body: String) -> Result<Token> {
+        let path = "/sky/issuer/token";
+
+        let mut request = Request::builder()
+            .method("POST")
+            .uri(format!("{}{}", self.concourse_uri, path))
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+
+        // Client credentials identify the client in the request body, every other grant is
+        // performed on behalf of the fly client.
+        if !matches!(self.auth, Authentication::ClientCredentials { .. }) {
+            request = request.header(AUTHORIZATION, FLY_CLIENT_CREDENTIALS);
+        }
+
+        let response = self.send("token", request.body(body.into())?).await?;
+        let status = response.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(error_from_response(response).await)
+        } else {
+            deserialize_json_response::<Token>(response).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::StatusCode;
+    use secstr::SecStr;
+
+    use crate::ci::{BuildName, JobName, PipelineName};
+    use crate::concourse::api::ConcourseAPI;
+    use crate::concourse::auth::Authentication;
+    use crate::concourse::build::BuildID;
+    use crate::concourse::ci::{ConcourseTeam, ConcourseUrl};
+    use crate::concourse::credentials::StaticSecret;
+    use crate::test_support::stand_in;
+
+    const RERUN: &str = r#"
+    {
+        "id": 3095,
+        "team_name": "radicle",
+        "name": "4.1",
+        "status": "pending",
+        "job_name": "build",
+        "pipeline_id": 101,
+        "pipeline_name": "heartwood-pipeline",
+        "rerun_number": 1,
+        "rerun_of": { "id": 3094, "name": "4" }
+    }"#;
+
+    fn api(url: String) -> ConcourseAPI {
+        ConcourseAPI::new(ConcourseUrl(url), Authentication::Bearer { token: Arc::new(StaticSecret(SecStr::from("t0k3n"))) })
+    }
+
+    #[tokio::test]
+    async fn will_abort_build_and_rerun_it() {
+        let (url, received) = stand_in(vec![(200, ""), (200, RERUN)]);
+        let mut api = api(url);
+
+        api.abort_build(&BuildID(3094)).await.unwrap();
+        let rerun = api.rerun_pipeline_job_build(
+            &ConcourseTeam(String::from("radicle")),
+            &PipelineName(String::from("heartwood-pipeline")),
+            &JobName(String::from("build")),
+            &BuildName(String::from("4")),
+        ).await.unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!((received[0].method.as_str(), received[0].path.as_str()), ("PUT", "/api/v1/builds/3094/abort"));
+        assert_eq!(received[0].headers["authorization"], "Bearer t0k3n");
+        assert_eq!((received[1].method.as_str(), received[1].path.as_str()), ("POST", "/api/v1/teams/radicle/pipelines/heartwood-pipeline/jobs/build/builds/4"));
+        assert_eq!(rerun.id, BuildID(3095));
+        assert!(rerun.is_rerun());
+    }
+
+    #[tokio::test]
+    async fn will_pause_archive_and_destroy_pipeline() {
+        let (url, received) = stand_in(vec![(200, ""), (200, ""), (404, "pipeline not found")]);
+        let mut api = api(url);
+        let team = ConcourseTeam(String::from("radicle"));
+        let pipeline = PipelineName(String::from("heartwood-pipeline"));
+
+        api.pause_pipeline(&team, &pipeline).await.unwrap();
+        api.archive_pipeline(&team, &pipeline).await.unwrap();
+        let destroyed = api.destroy_pipeline(&team, &pipeline).await;
+
+        let requests = received.lock().unwrap().iter().map(|request| format!("{} {}", request.method, request.path)).collect::<Vec<_>>();
+        assert_eq!(requests, vec![
+            "PUT /api/v1/teams/radicle/pipelines/heartwood-pipeline/pause",
+            "PUT /api/v1/teams/radicle/pipelines/heartwood-pipeline/archive",
+            "DELETE /api/v1/teams/radicle/pipelines/heartwood-pipeline",
+        ]);
+        assert_eq!(destroyed.unwrap_err().status(), Some(StatusCode::NOT_FOUND));
     }
 }
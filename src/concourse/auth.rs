@@ -0,0 +1,34 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use crate::concourse::credentials::CredentialProvider;
+
+/// How the broker authenticates against Concourse.
+#[derive(Clone)]
+pub enum Authentication {
+    /// Password grant for a Concourse local user, performed on behalf of the fly client.
+    Password {
+        user: String,
+        password: Arc<dyn CredentialProvider>,
+    },
+    /// A token issued out of band, e.g. by `fly login` against an SSO/OIDC provider. It cannot be
+    /// renewed by the broker, so it is read again from its source on every request.
+    Bearer {
+        token: Arc<dyn CredentialProvider>,
+    },
+    /// Client credentials grant for a client registered with the Concourse identity provider.
+    ClientCredentials {
+        client_id: String,
+        client_secret: Arc<dyn CredentialProvider>,
+    },
+}
+
+impl Debug for Authentication {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Authentication::Password { user, .. } => write!(f, "Password {{ user: {user} }}"),
+            Authentication::Bearer { .. } => write!(f, "Bearer"),
+            Authentication::ClientCredentials { client_id, .. } => write!(f, "ClientCredentials {{ client_id: {client_id} }}"),
+        }
+    }
+}
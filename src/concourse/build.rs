@@ -40,6 +40,13 @@ impl<'de> Deserialize<'de> for BuildStatus {
     }
 }
 
+/// Reference to the build a rerun was created from.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RerunOf {
+    pub id: BuildID,
+    pub name: String,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Build {
     pub id: BuildID,
@@ -53,6 +60,8 @@ pub struct Build {
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
     pub created_by: Option<String>,
+    pub rerun_number: Option<usize>,
+    pub rerun_of: Option<RerunOf>,
 }
 
 impl Build {
@@ -63,11 +72,15 @@ impl Build {
     pub fn has_completed_successfully(&self) -> bool {
         self.status == BuildStatus::Succeeded
     }
+
+    pub fn is_rerun(&self) -> bool {
+        self.rerun_of.is_some()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::concourse::build::{Build, BuildID, BuildStatus};
+    use crate::concourse::build::{Build, BuildID, BuildStatus, RerunOf};
     use crate::concourse::pipeline::PipelineID;
 
     #[test]
@@ -100,6 +113,69 @@ mod tests {
         assert_eq!(build.start_time, Some(1692021331));
         assert_eq!(build.end_time, Some(1692021336));
         assert_eq!(build.created_by, Some(String::from("test")));
+        assert_eq!(build.rerun_number, None);
+        assert_eq!(build.rerun_of, None);
+        assert!(!build.is_rerun());
+
+        Ok(())
+    }
+
+    #[test]
+    fn will_successfully_deserialize_a_rerun_build() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "id": 3101,
+            "team_name": "main",
+            "name": "4.1",
+            "status": "pending",
+            "api_url": "/api/v1/builds/3101",
+            "job_name": "poc-job",
+            "pipeline_id": 101,
+            "pipeline_name": "heartwood",
+            "rerun_number": 1,
+            "rerun_of": {
+                "id": 3094,
+                "name": "4"
+            },
+            "created_by": "test"
+        }"#;
+
+        let build = serde_json::from_str::<Build>(json)?;
+
+        assert_eq!(build.id, BuildID(3101));
+        assert_eq!(build.name, "4.1");
+        assert_eq!(build.status, BuildStatus::Pending);
+        assert_eq!(build.start_time, None);
+        assert_eq!(build.end_time, None);
+        assert_eq!(build.rerun_number, Some(1));
+        assert_eq!(build.rerun_of, Some(RerunOf { id: BuildID(3094), name: String::from("4") }));
+        assert!(build.is_rerun());
+
+        Ok(())
+    }
+
+    #[test]
+    fn will_successfully_deserialize_aborted_build() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "id": 3094,
+            "team_name": "main",
+            "name": "4",
+            "status": "aborted",
+            "api_url": "/api/v1/builds/3094",
+            "job_name": "poc-job",
+            "pipeline_id": 101,
+            "pipeline_name": "heartwood",
+            "start_time": 1692021331,
+            "end_time": 1692021333,
+            "created_by": "test"
+        }"#;
+
+        let build = serde_json::from_str::<Build>(json)?;
+
+        assert_eq!(build.status, BuildStatus::Aborted);
+        assert!(build.has_completed());
+        assert!(!build.has_completed_successfully());
 
         Ok(())
     }
@@ -1,15 +1,19 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::time::Duration;
 
-use anyhow::anyhow;
-use radicle_term as term;
+use hyper::StatusCode;
 use tokio::time::sleep;
+use tracing::{info, warn};
 
-use crate::ci::{CI, CIJob, CIResult, CIResultStatus, PipelineConfig, PipelineName, RadicleApiUrl};
+use crate::ci::{CI, CIBuild, CIJob, CIResult, CIResultStatus, JobName, JobResult, PipelineConfig, PipelineName, RadicleApiUrl};
 use crate::concourse::api::ConcourseAPI;
-use crate::concourse::build::{Build, BuildID};
+use crate::concourse::auth::Authentication;
+use crate::concourse::build::{Build, BuildID, BuildStatus};
+use crate::error::{AuthError, ConfigError, Error};
+use crate::template::render;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct ConcourseUrl(pub String);
 
 impl Display for ConcourseUrl {
@@ -18,11 +22,58 @@ impl Display for ConcourseUrl {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConcourseTeam(pub String);
+
+impl Display for ConcourseTeam {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// How builds are linked to in reports. The Concourse URL the broker talks to is often internal,
+/// e.g. `http://localhost:8080`, and of no use to remote contributors.
+#[derive(Clone, Debug)]
+pub enum BuildLinks {
+    /// The build page of the Concourse web UI served at the URL.
+    Concourse(ConcourseUrl),
+    /// A URL template with the placeholders `{{team}}`, `{{pipeline}}`, `{{job}}`, `{{build}}`,
+    /// i.e. the build name, and `{{id}}`, e.g. to link to a log viewer instead of Concourse.
+    Template(String),
+}
+
+/// The Concourse teams pipelines are created in. Every repository uses the default team unless it
+/// has been assigned a team of its own.
+#[derive(Clone, Debug)]
+pub struct ConcourseTeams {
+    pub default: ConcourseTeam,
+    /// Team overrides keyed by canonical repository id.
+    pub repositories: HashMap<String, ConcourseTeam>,
+}
+
+impl ConcourseTeams {
+    pub fn team_for(&self, project_id: &str) -> &ConcourseTeam {
+        self.repositories.get(project_id).unwrap_or(&self.default)
+    }
+
+    /// Returns every configured team once, starting with the default one.
+    pub fn all(&self) -> Vec<&ConcourseTeam> {
+        let mut teams = vec![&self.default];
+        for team in self.repositories.values() {
+            if !teams.contains(&team) {
+                teams.push(team);
+            }
+        }
+        teams
+    }
+}
+
 pub struct ConcourseCI {
     runtime: tokio::runtime::Runtime,
     api: ConcourseAPI,
     radicle_api_url: RadicleApiUrl,
-    concourse_url: ConcourseUrl,
+    links: BuildLinks,
+    teams: ConcourseTeams,
 }
 
 impl Clone for ConcourseCI {
@@ -31,36 +82,58 @@ impl Clone for ConcourseCI {
             runtime: tokio::runtime::Runtime::new().unwrap(),
             api: self.api.clone(),
             radicle_api_url: self.radicle_api_url.clone(),
-            concourse_url: self.concourse_url.clone(),
+            links: self.links.clone(),
+            teams: self.teams.clone(),
         }
     }
 }
 
 impl ConcourseCI {
-    pub fn new(radicle_api_url: RadicleApiUrl, concourse_url: ConcourseUrl, ci_user: String, ci_pass: String) -> Self {
+    pub fn new(radicle_api_url: RadicleApiUrl, concourse_url: ConcourseUrl, links: BuildLinks, auth: Authentication, teams: ConcourseTeams) -> Self {
         let runtime = tokio::runtime::Runtime::new().unwrap();
-        let api = ConcourseAPI::new(concourse_url.clone(), ci_user, ci_pass);
+        let api = ConcourseAPI::new(concourse_url, auth);
 
-        Self { runtime, api, concourse_url, radicle_api_url }
+        Self { runtime, api, links, radicle_api_url, teams }
     }
 
-    pub async fn watch_pipeline_job_build(&mut self, build_id: BuildID) -> Result<Build, anyhow::Error> {
-        loop {
-            sleep(Duration::from_secs(3)).await;
+    /// Verifies that the configured user is a member of every configured team, so that a
+    /// misconfiguration is reported on startup instead of on the first build.
+    pub fn validate_team_membership(&mut self) -> Result<(), Error> {
+        self.runtime.block_on(async {
+            let user = self.api.get_user_info()
+                .await
+                .map_err(|error| Error::concourse("Failed to get Concourse user info", error))?;
 
-            let build_result = self.api.get_build(&build_id).await;
-            match build_result {
-                Ok(build) => {
-                    if build.has_completed() {
-                        term::info!("Pipeline job build #{} has completed execution", build_id);
-                        break Ok(build);
-                    }
+            for team in self.teams.all() {
+                if !user.is_member_of(team) {
+                    return Err(AuthError::NotTeamMember { user: user.user_name.clone(), team: team.clone() }.into());
                 }
-                Err(error) => {
-                    term::info!("Failed to get pipeline job build {:#?}", error);
-                    break Err(anyhow::anyhow!("Failed to get pipeline job build"));
+            }
+
+            Ok(())
+        })
+    }
+
+    pub async fn watch_pipeline_job_build(&mut self, build_id: BuildID) -> Result<Build, Error> {
+        watch_build(&mut self.api, build_id).await
+    }
+}
+
+async fn watch_build(api: &mut ConcourseAPI, build_id: BuildID) -> Result<Build, Error> {
+    loop {
+        sleep(Duration::from_secs(3)).await;
+
+        let build_result = api.get_build(&build_id).await;
+        match build_result {
+            Ok(build) => {
+                if build.has_completed() {
+                    info!(build = %build_id, status = ?build.status, "Pipeline job build has completed");
+                    break Ok(build);
                 }
             }
+            Err(error) => {
+                break Err(Error::concourse(format!("Failed to get pipeline job build #{build_id}"), error));
+            }
         }
     }
 }
@@ -70,90 +143,256 @@ fn create_concourse_pipeline_config(radicle_api_url: &RadicleApiUrl, job: &CIJob
 
     job.pipeline_config
         .replace("((repo_url))", repo_url.as_str())
-        .replace("((patch_revision_id))", job.patch_revision_id.as_str())
+        .replace("((patch_revision_id))", job.patch_revision_id.as_deref().unwrap_or_default())
         .replace("((patch_head))", job.patch_head.as_str())
+        .replace("((branch))", job.branch.as_deref().unwrap_or_default())
+        .replace("((tag_name))", job.tag_name.as_deref().unwrap_or_default())
+}
+
+/// Patch builds run in a pipeline of their own, so that patches built at the same time do not
+/// overwrite each other's pipeline configuration.
+fn pipeline_name(job: &CIJob) -> PipelineName {
+    match (&job.tag_name, &job.patch_id, &job.branch) {
+        (Some(tag), _, _) => release_pipeline_name(&job.project_id, tag),
+        (None, Some(patch_id), _) => patch_pipeline_name(&job.project_id, patch_id),
+        (None, None, Some(branch)) => branch_pipeline_name(&job.project_id, branch),
+        (None, None, None) => PipelineName(format!("{}-pipeline", job.project_id)),
+    }
+}
+
+fn branch_pipeline_name(project_id: &str, branch: &str) -> PipelineName {
+    PipelineName(format!("{project_id}-branch-{}-pipeline", escape_ref(branch)))
+}
+
+/// Every tag is built in a pipeline of its own, as its name and head are part of the pipeline
+/// configuration.
+fn release_pipeline_name(project_id: &str, tag: &str) -> PipelineName {
+    PipelineName(format!("{project_id}-release-{}-pipeline", escape_ref(tag)))
+}
+
+/// Slashes of branches and tags such as `release/1.x` are not valid in pipeline names.
+fn escape_ref(name: &str) -> String {
+    name.replace('/', "-")
+}
+
+fn patch_pipeline_name(project_id: &str, patch_id: &str) -> PipelineName {
+    PipelineName(format!("{project_id}-patch-{patch_id}-pipeline"))
 }
 
 impl CI for ConcourseCI {
-    fn setup(&mut self, job: CIJob) -> Result<PipelineName, anyhow::Error> {
+    fn setup(&mut self, job: &CIJob) -> Result<PipelineName, Error> {
         self.runtime.block_on(async {
-            let concourse_config = create_concourse_pipeline_config(&self.radicle_api_url, &job);
-            let pipeline_name = PipelineName(format!("{}-pipeline", job.project_id));
+            let concourse_config = create_concourse_pipeline_config(&self.radicle_api_url, job);
+            let pipeline_name = pipeline_name(job);
+            let team = self.teams.team_for(&job.project_id).clone();
 
-            let result = self.api.get_access_token().await;
-            if result.is_err() {
-                return Err(anyhow::anyhow!("Failed to get access token"));
-            }
-
-            let result = self.api.get_pipeline_config(&pipeline_name).await;
+            let result = self.api.get_pipeline_config(&team, &pipeline_name).await;
             let config_version = match result {
                 Ok(config) => config.version,
                 Err(_) => None,
             };
 
-            term::info!("Triggering pipeline {} creation with current version {:?}", pipeline_name, config_version);
-            let result = self.api.create_pipeline_config(&pipeline_name, concourse_config, config_version).await;
-            if result.is_err() {
-                term::info!("Failed to create pipeline {} {:?}", pipeline_name, result);
+            info!(pipeline = %pipeline_name, version = ?config_version, "Creating pipeline");
+            let result = self.api.create_pipeline_config(&team, &pipeline_name, concourse_config, config_version).await;
+            if let Err(error) = result {
+                warn!(pipeline = %pipeline_name, %error, "Failed to create pipeline");
             }
 
-            term::info!("Unpausing pipeline {}", pipeline_name);
-            let result = self.api.unpause_pipeline(&pipeline_name).await;
-            if result.is_err() {
-                return Err(anyhow::anyhow!("Failed to unpause pipeline {}", pipeline_name));
-            }
+            info!(pipeline = %pipeline_name, %team, "Unpausing pipeline");
+            self.api.unpause_pipeline(&team, &pipeline_name)
+                .await
+                .map_err(|error| Error::concourse(format!("Failed to unpause pipeline {pipeline_name}"), error))?;
 
             Ok(pipeline_name)
         })
     }
 
-    fn run_pipeline(&mut self, pipeline_name: &PipelineName) -> Result<CIResult, anyhow::Error> {
+    fn trigger_pipeline(&mut self, job: &CIJob, pipeline_name: &PipelineName) -> Result<CIBuild, Error> {
         self.runtime.block_on(async {
-            let concourse_url = &self.concourse_url.clone();
-            let result = self.api.get_all_pipeline_jobs(pipeline_name)
+            let team = self.teams.team_for(&job.project_id).clone();
+            let jobs = self.api.get_all_pipeline_jobs(&team, pipeline_name)
                 .await
-                .map(|jobs| jobs.get(0).unwrap().get_name());
-            if result.is_err() {
-                return Err(anyhow!("Cannot find jobs for {} pipeline",pipeline_name));
-            }
+                .map_err(|error| Error::concourse(format!("Cannot find jobs for {pipeline_name} pipeline"), error))?;
+            let job_name = jobs.first()
+                .map(|job| job.get_name())
+                .ok_or(ConfigError::NoPipelineJobs(pipeline_name.to_string()))?;
+
+            let build = self.api.trigger_new_pipeline_job_build(&team, pipeline_name, &job_name)
+                .await
+                .map_err(|error| Error::concourse(format!("Cannot trigger job {job_name} build for {pipeline_name} pipeline"), error))?;
 
-            let job_name = result.unwrap();
+            Ok(CIBuild { id: build.id.0, url: build_url(&self.links, &build) })
+        })
+    }
 
-            let build_result = self.api.trigger_new_pipeline_job_build(pipeline_name, &job_name).await;
-            if build_result.is_err() {
-                return Err(anyhow!("Cannot trigger job {} build for {} pipeline", job_name, pipeline_name));
+    fn trigger_pipeline_job(&mut self, job: &CIJob, pipeline_name: &PipelineName, job_name: &JobName) -> Result<CIBuild, Error> {
+        self.runtime.block_on(async {
+            let team = self.teams.team_for(&job.project_id).clone();
+            let jobs = self.api.get_all_pipeline_jobs(&team, pipeline_name)
+                .await
+                .map_err(|error| Error::concourse(format!("Cannot find jobs for {pipeline_name} pipeline"), error))?;
+            if !jobs.iter().any(|job| job.is_named(job_name)) {
+                return Err(ConfigError::PipelineJobNotFound { pipeline: pipeline_name.to_string(), job: job_name.to_string() }.into());
             }
-            let build = build_result.unwrap();
-
-
-            let watch_build_result = loop {
-                sleep(Duration::from_secs(3)).await;
-                let build_result = self.api.get_build(&build.id).await;
-                match build_result {
-                    Ok(build) => {
-                        if build.has_completed() {
-                            term::info!("Pipeline job build #{} has completed execution", build.id);
-                            break Ok(build);
-                        }
-                    }
-                    Err(error) => {
-                        term::info!("Failed to get pipeline job build {:#?}", error);
-                        break Err(anyhow::anyhow!("Failed to get pipeline job build"));
-                    }
+
+            let build = self.api.trigger_pipeline_job(&team, pipeline_name, job_name)
+                .await
+                .map_err(|error| Error::concourse(format!("Cannot trigger job {job_name} build for {pipeline_name} pipeline"), error))?;
+
+            Ok(CIBuild { id: build.id.0, url: build_url(&self.links, &build) })
+        })
+    }
+
+    fn watch_build(&mut self, build: &CIBuild) -> Result<CIResult, Error> {
+        self.runtime.block_on(async {
+            let build = watch_build(&mut self.api, BuildID(build.id)).await?;
+            let team = ConcourseTeam(build.team_name.clone());
+            let pipeline_name = PipelineName(build.pipeline_name.clone());
+
+            // The job breakdown is informational, a build that has completed is not failed over it.
+            let jobs = match self.api.get_all_pipeline_jobs(&team, &pipeline_name).await {
+                Ok(jobs) => jobs.iter()
+                    .filter(|job| job.has_completed())
+                    .filter(|job| !matches!(job.get_status(), BuildStatus::Unknown(_)))
+                    .map(|job| JobResult { name: job.get_name(), status: result_status(&job.get_status()) })
+                    .collect(),
+                Err(error) => {
+                    warn!(pipeline = %pipeline_name, %error, "Failed to get jobs of pipeline");
+                    Vec::new()
                 }
             };
 
-            watch_build_result.map(|build| {
-                CIResult {
-                    status: if build.has_completed_successfully() { CIResultStatus::Success } else { CIResultStatus::Failure },
-                    url: format!("{}/teams/main/pipelines/{}/jobs/{}/builds/{}",
-                                 concourse_url,
-                                 build.pipeline_name,
-                                 build.job_name,
-                                 build.name,
-                    ),
-                }
+            Ok(CIResult {
+                status: result_status(&build.status),
+                url: build_url(&self.links, &build),
+                jobs,
+                duration: build_duration(&build),
             })
         })
     }
+
+    fn abort_build(&mut self, build: &CIBuild) -> Result<(), Error> {
+        self.runtime.block_on(async {
+            info!(build = build.id, "Aborting pipeline job build");
+            self.api.abort_build(&BuildID(build.id))
+                .await
+                .map_err(|error| Error::concourse(format!("Failed to abort pipeline job build #{}", build.id), error))
+        })
+    }
+
+    fn destroy_patch_pipeline(&mut self, project_id: &str, patch_id: &str) -> Result<(), Error> {
+        self.runtime.block_on(async {
+            let team = self.teams.team_for(project_id).clone();
+            let pipeline_name = patch_pipeline_name(project_id, patch_id);
+
+            info!(pipeline = %pipeline_name, %team, "Destroying pipeline");
+            match self.api.destroy_pipeline(&team, &pipeline_name).await {
+                Err(error) if error.status() == Some(StatusCode::NOT_FOUND) => Ok(()),
+                result => result.map_err(|error| Error::concourse(format!("Failed to destroy pipeline {pipeline_name}"), error)),
+            }
+        })
+    }
+}
+
+fn result_status(status: &BuildStatus) -> CIResultStatus {
+    match status {
+        BuildStatus::Succeeded => CIResultStatus::Success,
+        BuildStatus::Aborted => CIResultStatus::Aborted,
+        BuildStatus::Errored => CIResultStatus::Errored,
+        _ => CIResultStatus::Failure,
+    }
+}
+
+fn build_duration(build: &Build) -> Option<u64> {
+    match (build.start_time, build.end_time) {
+        (Some(start), Some(end)) => u64::try_from(end - start).ok(),
+        _ => None,
+    }
+}
+
+fn build_url(links: &BuildLinks, build: &Build) -> String {
+    match links {
+        BuildLinks::Concourse(concourse_url) => format!("{}/teams/{}/pipelines/{}/jobs/{}/builds/{}",
+            concourse_url,
+            build.team_name,
+            build.pipeline_name,
+            build.job_name,
+            build.name,
+        ),
+        BuildLinks::Template(template) => render(template, &[
+            ("team", &build.team_name),
+            ("pipeline", &build.pipeline_name),
+            ("job", &build.job_name),
+            ("build", &build.name),
+            ("id", &build.id.to_string()),
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ci::{CIJob, PipelineConfig, PipelineName, RadicleApiUrl};
+    use crate::concourse::build::Build;
+    use crate::concourse::ci::{branch_pipeline_name, build_url, create_concourse_pipeline_config, pipeline_name, release_pipeline_name, BuildLinks, ConcourseUrl};
+
+    fn job(patch_id: Option<&str>, branch: Option<&str>, tag_name: Option<&str>) -> CIJob {
+        CIJob {
+            patch_revision_id: patch_id.map(|_| String::from("a1b2c3")),
+            patch_head: String::from("d4e5f6"),
+            project_id: String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5"),
+            pipeline_config: PipelineConfig(String::from("revision: ((patch_revision_id)), branch: ((branch)), tag: ((tag_name))")),
+            patch_id: patch_id.map(String::from),
+            branch: branch.map(String::from),
+            tag_name: tag_name.map(String::from),
+        }
+    }
+
+    #[test]
+    fn will_name_pipelines_after_the_kind_of_build() {
+        assert_eq!(pipeline_name(&job(None, Some("master"), None)), PipelineName(String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5-branch-master-pipeline")));
+        assert_eq!(pipeline_name(&job(Some("f0b0c8"), None, None)), PipelineName(String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5-patch-f0b0c8-pipeline")));
+        assert_eq!(pipeline_name(&job(None, None, Some("v1.0.0"))), PipelineName(String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5-release-v1.0.0-pipeline")));
+    }
+
+    #[test]
+    fn will_only_expose_the_variables_of_the_kind_of_build() {
+        let radicle_api_url = RadicleApiUrl(String::from("http://localhost:8081"));
+
+        assert_eq!(create_concourse_pipeline_config(&radicle_api_url, &job(Some("f0b0c8"), None, None)).0, "revision: a1b2c3, branch: , tag: ");
+        assert_eq!(create_concourse_pipeline_config(&radicle_api_url, &job(None, Some("master"), None)).0, "revision: , branch: master, tag: ");
+    }
+
+    #[test]
+    fn will_name_branch_pipelines_after_the_branch() {
+        assert_eq!(branch_pipeline_name("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "master"), PipelineName(String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5-branch-master-pipeline")));
+        assert_eq!(branch_pipeline_name("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "release/1.x"), PipelineName(String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5-branch-release-1.x-pipeline")));
+    }
+
+    #[test]
+    fn will_name_release_pipelines_after_the_tag() {
+        assert_eq!(release_pipeline_name("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "v1.0.0"), PipelineName(String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5-release-v1.0.0-pipeline")));
+        assert_eq!(release_pipeline_name("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "cli/v0.2"), PipelineName(String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5-release-cli-v0.2-pipeline")));
+    }
+
+    #[test]
+    fn will_link_builds_to_the_public_url_or_template() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "id": 3094,
+            "team_name": "main",
+            "name": "4",
+            "status": "succeeded",
+            "job_name": "build",
+            "pipeline_id": 101,
+            "pipeline_name": "heartwood-pipeline"
+        }"#;
+        let build = serde_json::from_str::<Build>(json)?;
+
+        let concourse = BuildLinks::Concourse(ConcourseUrl(String::from("https://ci.example.com")));
+        let template = BuildLinks::Template(String::from("https://radicle-ci.example.com/builds/{{id}}?job={{pipeline}}/{{job}}"));
+
+        assert_eq!(build_url(&concourse, &build), "https://ci.example.com/teams/main/pipelines/heartwood-pipeline/jobs/build/builds/4");
+        assert_eq!(build_url(&template, &build), "https://radicle-ci.example.com/builds/3094?job=heartwood-pipeline/build");
+        Ok(())
+    }
 }
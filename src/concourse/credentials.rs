@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::process::Command;
+use std::{env, fs};
+
+use secstr::SecStr;
+use serde::Deserialize;
+
+use crate::error::AuthError;
+
+/// The environment variable the Concourse password or client secret is read from when no other
+/// source is given.
+pub const CONCOURSE_PASS_ENV: &str = "RADICLE_CI_CONCOURSE_PASS";
+
+/// The environment variable a pre-issued Concourse bearer token is read from.
+pub const CONCOURSE_TOKEN_ENV: &str = "RADICLE_CI_CONCOURSE_TOKEN";
+
+/// A source of a Concourse secret, i.e. a password, a client secret or a pre-issued token. The
+/// secret is requested every time it is needed, so providers are free to pick up a rotated one.
+pub trait CredentialProvider: Send + Sync {
+    fn secret(&self) -> Result<SecStr, AuthError>;
+}
+
+/// A secret that is already known, e.g. one provided programmatically.
+pub struct StaticSecret(pub SecStr);
+
+impl CredentialProvider for StaticSecret {
+    fn secret(&self) -> Result<SecStr, AuthError> {
+        Ok(self.0.clone())
+    }
+}
+
+impl Debug for StaticSecret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StaticSecret(***)")
+    }
+}
+
+/// Reads the secret from a file, such as a mounted container secret. A single trailing newline is
+/// ignored.
+#[derive(Debug)]
+pub struct SecretFile(pub PathBuf);
+
+impl CredentialProvider for SecretFile {
+    fn secret(&self) -> Result<SecStr, AuthError> {
+        let content = fs::read(&self.0)
+            .map_err(|source| AuthError::SecretFile { path: self.0.clone(), source })?;
+
+        Ok(SecStr::new(trim_trailing_newline(content)))
+    }
+}
+
+/// Reads the secret from an environment variable.
+#[derive(Debug)]
+pub struct SecretEnv(pub String);
+
+impl CredentialProvider for SecretEnv {
+    fn secret(&self) -> Result<SecStr, AuthError> {
+        env::var(&self.0)
+            .map(SecStr::from)
+            .map_err(|_| AuthError::SecretEnv(self.0.clone()))
+    }
+}
+
+/// Runs a command and uses its standard output as the secret. This is how secret stores such as
+/// `pass` or `vault` can be plugged in without the broker knowing about them.
+#[derive(Debug)]
+pub struct SecretCommand(pub String);
+
+impl CredentialProvider for SecretCommand {
+    fn secret(&self) -> Result<SecStr, AuthError> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(&self.0)
+            .output()
+            .map_err(|source| AuthError::SecretCommand { command: self.0.clone(), source })?;
+
+        if !output.status.success() {
+            return Err(AuthError::SecretCommandFailed { command: self.0.clone(), status: output.status });
+        }
+
+        Ok(SecStr::new(trim_trailing_newline(output.stdout)))
+    }
+}
+
+/// Reads the bearer token `fly login` stored for a target in a `.flyrc` file:
+///
+/// ```yaml
+/// targets:
+///   ci:
+///     api: https://ci.example.com
+///     team: main
+///     token:
+///       type: bearer
+///       value: eyJhbGciOi...
+/// ```
+#[derive(Debug)]
+pub struct FlyrcToken {
+    pub path: PathBuf,
+    pub target: String,
+}
+
+impl FlyrcToken {
+    /// Uses the `.flyrc` file in the home directory of the current user.
+    pub fn from_home(target: String) -> Result<Self, AuthError> {
+        let home = env::var_os("HOME").ok_or(AuthError::NoHome)?;
+        Ok(Self { path: PathBuf::from(home).join(".flyrc"), target })
+    }
+}
+
+impl CredentialProvider for FlyrcToken {
+    fn secret(&self) -> Result<SecStr, AuthError> {
+        let content = fs::read_to_string(&self.path)
+            .map_err(|source| AuthError::ReadFlyrc { path: self.path.clone(), source })?;
+
+        parse_flyrc_token(&content, &self.target)
+            .map_err(|source| AuthError::ParseFlyrc { path: self.path.clone(), source })?
+            .map(SecStr::from)
+            .ok_or_else(|| AuthError::NoFlyrcToken { target: self.target.clone(), path: self.path.clone() })
+    }
+}
+
+/// The parts of a `.flyrc` file the broker needs, every other key is ignored.
+#[derive(Debug, Deserialize)]
+struct Flyrc {
+    #[serde(default)]
+    targets: HashMap<String, FlyrcTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlyrcTarget {
+    token: Option<FlyrcTargetToken>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlyrcTargetToken {
+    value: String,
+}
+
+fn parse_flyrc_token(content: &str, target: &str) -> Result<Option<String>, serde_yaml::Error> {
+    let mut flyrc = serde_yaml::from_str::<Flyrc>(content)?;
+
+    Ok(flyrc.targets.remove(target).and_then(|target| target.token).map(|token| token.value))
+}
+
+fn trim_trailing_newline(mut content: Vec<u8>) -> Vec<u8> {
+    if content.ends_with(b"\n") {
+        content.pop();
+        if content.ends_with(b"\r") {
+            content.pop();
+        }
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use secstr::SecStr;
+
+    use crate::concourse::credentials::{CredentialProvider, parse_flyrc_token, SecretCommand, SecretEnv, SecretFile, StaticSecret};
+    use crate::error::AuthError;
+    use crate::test_support::TempPath;
+
+    const FLYRC: &str = r#"
+targets:
+  ci:
+    api: https://ci.example.com
+    team: main
+    token:
+      type: bearer
+      value: ci-token
+  local:
+    api: http://localhost:8080
+    team: main
+    token:
+      type: bearer
+      value: "local-token"
+"#;
+
+    #[test]
+    fn will_return_static_password() -> Result<(), AuthError> {
+        let provider = StaticSecret(SecStr::from("p&ss=word"));
+
+        assert_eq!(provider.secret()?, SecStr::from("p&ss=word"));
+        Ok(())
+    }
+
+    #[test]
+    fn will_read_password_from_file_without_trailing_newline() -> Result<(), AuthError> {
+        let path = TempPath::with_content("password", "secret\n");
+
+        let password = SecretFile(path.to_path_buf()).secret();
+
+        assert_eq!(password?, SecStr::from("secret"));
+        Ok(())
+    }
+
+    #[test]
+    fn will_return_an_error_if_password_file_is_missing() {
+        let provider = SecretFile("/non/existent/password".into());
+
+        assert!(provider.secret().is_err());
+    }
+
+    #[test]
+    fn will_return_an_error_if_environment_variable_is_not_set() {
+        let provider = SecretEnv(String::from("RADICLE_CI_TEST_UNSET_PASSWORD"));
+
+        assert!(provider.secret().is_err());
+    }
+
+    #[test]
+    fn will_read_password_from_command_output() -> Result<(), AuthError> {
+        let provider = SecretCommand(String::from("echo secret"));
+
+        assert_eq!(provider.secret()?, SecStr::from("secret"));
+        Ok(())
+    }
+
+    #[test]
+    fn will_parse_flyrc_token_of_target() -> Result<(), serde_yaml::Error> {
+        assert_eq!(parse_flyrc_token(FLYRC, "ci")?, Some(String::from("ci-token")));
+        assert_eq!(parse_flyrc_token(FLYRC, "local")?, Some(String::from("local-token")));
+        Ok(())
+    }
+
+    #[test]
+    fn will_not_parse_flyrc_token_of_unknown_target() -> Result<(), serde_yaml::Error> {
+        assert_eq!(parse_flyrc_token(FLYRC, "production")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn will_not_parse_flyrc_token_of_target_without_token() -> Result<(), serde_yaml::Error> {
+        let flyrc = r#"
+targets:
+  ci:
+    api: https://ci.example.com
+    team: main
+    ca_cert: |
+      token:
+        value: not-a-token
+  local:
+    token:
+      value: local-token
+"#;
+
+        assert_eq!(parse_flyrc_token(flyrc, "ci")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn will_return_an_error_if_flyrc_is_not_yaml() {
+        assert!(parse_flyrc_token("targets: [ci", "ci").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::string::FromUtf8Error;
+
+use hyper::{Body, Response, StatusCode};
+use hyper::body::HttpBody;
+
+use crate::concourse::response_error::ResponseError;
+
+/// Error bodies are only used for reporting, so anything beyond this size is dropped.
+const MAX_ERROR_BODY_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConcourseError {
+    /// Concourse responded with a 4xx or 5xx status.
+    #[error("Concourse responded with {status}: {error}")]
+    Response { status: StatusCode, error: ResponseError },
+    #[error("failed to obtain Concourse credentials: {0}")]
+    Credentials(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("invalid Concourse access token: {0}")]
+    InvalidToken(#[from] FromUtf8Error),
+    #[error("request to Concourse failed: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("invalid request to Concourse: {0}")]
+    Request(#[from] hyper::http::Error),
+    #[error("unexpected response from Concourse: {0}")]
+    Deserialize(#[from] serde_json::Error),
+}
+
+impl ConcourseError {
+    pub fn credentials<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Self {
+        ConcourseError::Credentials(error.into())
+    }
+
+    /// The HTTP status Concourse responded with, if the request got that far.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ConcourseError::Response { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+/// Turns a 4xx or 5xx response into an error. Concourse answers either with a JSON body shaped like
+/// [`ResponseError`] or with plain text, and may use chunked transfer encoding, so the body is read
+/// frame by frame up to [`MAX_ERROR_BODY_SIZE`] instead of relying on `Content-Length`.
+pub async fn error_from_response(response: Response<Body>) -> ConcourseError {
+    let status = response.status();
+    let body = match read_capped_body(response.into_body(), MAX_ERROR_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(error) => return ConcourseError::Http(error),
+    };
+
+    let error = serde_json::from_slice::<ResponseError>(&body).unwrap_or_else(|_| ResponseError {
+        errors: vec![String::from_utf8_lossy(&body).trim().to_string()],
+        warnings: None,
+    });
+
+    ConcourseError::Response { status, error }
+}
+
+async fn read_capped_body(mut body: Body, limit: usize) -> Result<Vec<u8>, hyper::Error> {
+    let mut content = Vec::new();
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let remaining = limit - content.len();
+        if chunk.len() >= remaining {
+            content.extend_from_slice(&chunk[..remaining]);
+            break;
+        }
+        content.extend_from_slice(&chunk);
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Body, Response, StatusCode};
+
+    use crate::concourse::error::{ConcourseError, error_from_response, MAX_ERROR_BODY_SIZE};
+
+    #[tokio::test]
+    async fn will_read_plain_text_error_without_content_length() {
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("pipeline not found\n"))
+            .unwrap();
+
+        let error = error_from_response(response).await;
+
+        assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+        match error {
+            ConcourseError::Response { error, .. } => assert_eq!(error.errors, vec![String::from("pipeline not found")]),
+            _ => panic!("expected response error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn will_read_chunked_json_error() {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data(r#"{"errors": ["malformed "#.into()).await.unwrap();
+            sender.send_data(r#"config"], "warnings": [{"type": "pipeline", "message": "deprecated"}]}"#.into()).await.unwrap();
+        });
+        let response = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(body)
+            .unwrap();
+
+        let error = error_from_response(response).await;
+
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+        match error {
+            ConcourseError::Response { error, .. } => {
+                assert_eq!(error.errors, vec![String::from("malformed config")]);
+                assert_eq!(error.warnings.unwrap()[0].message, "deprecated");
+            }
+            _ => panic!("expected response error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn will_cap_error_body_size() {
+        let response = Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("x".repeat(MAX_ERROR_BODY_SIZE * 2)))
+            .unwrap();
+
+        let error = error_from_response(response).await;
+
+        match error {
+            ConcourseError::Response { error, .. } => assert_eq!(error.errors[0].len(), MAX_ERROR_BODY_SIZE),
+            _ => panic!("expected response error"),
+        }
+    }
+}
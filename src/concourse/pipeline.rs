@@ -83,4 +83,29 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn will_successfully_deserialize_paused_and_archived_pipeline() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "id": 101,
+            "name": "heartwood",
+            "paused": true,
+            "paused_by": "test",
+            "paused_at": 1692021400,
+            "public": false,
+            "archived": true,
+            "team_name": "radicle",
+            "last_updated": 1692021169
+        }"#;
+
+        let pipeline = serde_json::from_str::<Pipeline>(json)?;
+
+        assert_eq!(pipeline.id, PipelineID(101));
+        assert!(pipeline.paused);
+        assert!(pipeline.archived);
+        assert_eq!(pipeline.team_name, "radicle");
+
+        Ok(())
+    }
+}
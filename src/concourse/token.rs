@@ -1,5 +1,5 @@
 use std::string::FromUtf8Error;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use secstr::SecStr;
 use serde::Deserialize;
@@ -25,6 +25,10 @@ pub struct Token {
     #[serde(deserialize_with = "deserialize_to_duration")]
     pub expires_in: std::time::Duration,
     pub id_token: String,
+    /// Only returned when the `offline_access` scope was requested. It allows renewing the access
+    /// token without sending the user credentials again.
+    #[serde(default)]
+    pub refresh_token: Option<SecStr>,
     #[serde(skip)]
     #[serde(default)]
     pub token_type: TokenType,
@@ -34,14 +38,37 @@ pub struct Token {
 }
 
 impl Token {
+    /// Wraps a token that was issued out of band. Since its lifetime is unknown to the broker it is
+    /// considered expired right away, so that it is never cached.
+    pub fn pre_issued(access_token: SecStr) -> Self {
+        Token {
+            access_token,
+            expires_in: Duration::ZERO,
+            id_token: String::new(),
+            refresh_token: None,
+            token_type: TokenType::Bearer,
+            created_at: SystemTime::now(),
+        }
+    }
+
     pub fn get_access_token(&self) -> Result<String, FromUtf8Error> {
         String::from_utf8(self.access_token.unsecure().to_vec())
     }
 
+    pub fn get_refresh_token(&self) -> Option<Result<String, FromUtf8Error>> {
+        self.refresh_token.as_ref().map(|token| String::from_utf8(token.unsecure().to_vec()))
+    }
+
     pub fn has_expired(&self) -> bool {
+        self.expires_within(Duration::ZERO)
+    }
+
+    /// Returns true if the token will have expired once the given margin has passed. Used to renew
+    /// the token before requests start failing.
+    pub fn expires_within(&self, margin: Duration) -> bool {
         let now = SystemTime::now();
         let expires_at = self.created_at + self.expires_in;
-        now > expires_at
+        now + margin > expires_at
     }
 }
 
@@ -76,10 +103,30 @@ mod tests {
         assert_eq!(token.access_token, secstr::SecStr::from("token"));
         assert_eq!(token.expires_in, std::time::Duration::from_secs(123456));
         assert_eq!(token.id_token, String::from("token-id"));
+        assert_eq!(token.refresh_token, None);
         assert_eq!(token.token_type, TokenType::Bearer);
         Ok(())
     }
 
+    #[test]
+    fn will_successfully_deserialize_token_with_refresh_token() -> Result<(), serde_json::Error> {
+        let string = r#"
+            {
+                "access_token": "token",
+                "expires_in": 123456,
+                "id_token": "token-id",
+                "refresh_token": "refresh-token",
+                "token_type": "bearer"
+            }
+        "#;
+
+        let token: Token = serde_json::from_str(string)?;
+
+        assert_eq!(token.refresh_token, Some(secstr::SecStr::from("refresh-token")));
+        assert_eq!(token.get_refresh_token().unwrap().unwrap(), "refresh-token");
+        Ok(())
+    }
+
     #[test]
     fn will_return_an_error_if_expires_in_is_a_string() -> Result<(), serde_json::Error> {
         let string = r#"
@@ -120,6 +167,7 @@ mod tests {
             access_token: "access-token".parse().unwrap(),
             expires_in: Duration::from_secs(10),
             id_token: "id-token".to_string(),
+            refresh_token: None,
             token_type: TokenType::Bearer,
             created_at: SystemTime::now(),
         };
@@ -133,10 +181,27 @@ mod tests {
             access_token: "access-token".parse().unwrap(),
             expires_in: Duration::from_secs(1),
             id_token: "id-token".to_string(),
+            refresh_token: None,
             token_type: TokenType::Bearer,
             created_at: SystemTime::now() - Duration::from_secs(2),
         };
 
         assert!(token.has_expired());
     }
+
+    #[test]
+    fn will_expire_within_margin_when_expiry_is_closer_than_margin() {
+        let token = Token {
+            access_token: "access-token".parse().unwrap(),
+            expires_in: Duration::from_secs(30),
+            id_token: "id-token".to_string(),
+            refresh_token: None,
+            token_type: TokenType::Bearer,
+            created_at: SystemTime::now(),
+        };
+
+        assert!(!token.has_expired());
+        assert!(token.expires_within(Duration::from_secs(60)));
+        assert!(!token.expires_within(Duration::from_secs(10)));
+    }
 }
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::concourse::ci::ConcourseTeam;
+
+#[derive(Debug, Deserialize)]
+pub struct UserInfo {
+    pub sub: Option<String>,
+    pub name: Option<String>,
+    pub user_id: String,
+    pub user_name: String,
+    pub email: Option<String>,
+    pub is_admin: bool,
+    pub is_system: bool,
+    /// The teams the user belongs to, mapped to the roles the user holds in each of them.
+    pub teams: HashMap<String, Vec<String>>,
+}
+
+impl UserInfo {
+    /// Admin users are allowed to act on every team even if they are not explicitly a member.
+    pub fn is_member_of(&self, team: &ConcourseTeam) -> bool {
+        self.is_admin || self.teams.contains_key(&team.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::concourse::ci::ConcourseTeam;
+    use crate::concourse::user::UserInfo;
+
+    #[test]
+    fn will_successfully_deserialize_user_info() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "sub": "Cg10ZXN0EgVsb2NhbA",
+            "name": "",
+            "user_id": "test",
+            "user_name": "test",
+            "email": "",
+            "is_admin": false,
+            "is_system": false,
+            "teams": {
+                "main": ["owner"],
+                "radicle": ["member", "viewer"]
+            },
+            "display_user_id": "test"
+        }"#;
+
+        let user = serde_json::from_str::<UserInfo>(json)?;
+
+        assert_eq!(user.user_id, "test");
+        assert_eq!(user.user_name, "test");
+        assert!(!user.is_admin);
+        assert!(!user.is_system);
+        assert_eq!(user.teams.get("main"), Some(&vec![String::from("owner")]));
+        assert_eq!(user.teams.get("radicle"), Some(&vec![String::from("member"), String::from("viewer")]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn will_be_member_only_of_listed_teams() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "user_id": "test",
+            "user_name": "test",
+            "is_admin": false,
+            "is_system": false,
+            "teams": { "radicle": ["member"] }
+        }"#;
+
+        let user = serde_json::from_str::<UserInfo>(json)?;
+
+        assert!(user.is_member_of(&ConcourseTeam(String::from("radicle"))));
+        assert!(!user.is_member_of(&ConcourseTeam(String::from("main"))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn will_be_member_of_every_team_when_admin() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "user_id": "admin",
+            "user_name": "admin",
+            "is_admin": true,
+            "is_system": false,
+            "teams": {}
+        }"#;
+
+        let user = serde_json::from_str::<UserInfo>(json)?;
+
+        assert!(user.is_member_of(&ConcourseTeam(String::from("main"))));
+
+        Ok(())
+    }
+}
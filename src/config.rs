@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::error::ConfigError;
+
+/// Broker configuration loaded from the file given with `--config`. It holds the settings that
+/// cannot be expressed through command line options, such as per-repository overrides. It is
+/// served by the status server, so secrets and where they are kept are left out when serialized.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BrokerConfig {
+    /// Per-repository settings keyed by repository id, with or without the `rad:` prefix.
+    #[serde(default)]
+    pub repositories: HashMap<String, RepositoryConfig>,
+    /// File the results of branch builds are kept in. Results are only kept in memory when not set.
+    pub results_file: Option<PathBuf>,
+    /// Where build statuses are reported to. Builds are reported with patch comments when not set.
+    pub reporters: Option<Vec<ReporterConfig>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReporterConfig {
+    /// A status comment on the patch revision built.
+    PatchComment,
+    /// A signed JSON payload posted to `url` for every status of every build.
+    Webhook {
+        /// Webhook URLs often carry a token, e.g. those of chat services.
+        #[serde(serialize_with = "redact")]
+        url: String,
+        /// File holding the secret shared with the receiver, used to sign the payloads.
+        #[serde(skip_serializing)]
+        secret_file: PathBuf,
+        /// File payloads that could not be delivered are appended to. They are only logged when
+        /// not set.
+        dead_letter_file: Option<PathBuf>,
+        /// How many times a delivery is attempted before giving up. Defaults to 3.
+        max_attempts: Option<u32>,
+    },
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RepositoryConfig {
+    /// The Concourse team the repository pipelines are created in. Falls back to the global
+    /// `--concourse-team` when not set.
+    pub concourse_team: Option<String>,
+    /// DIDs of users, besides the repository delegates, allowed to give CI commands such as
+    /// `/ci rerun` in patch comments.
+    #[serde(default)]
+    pub ci_command_users: Vec<String>,
+    /// Builds the default branch whenever a delegate pushes to it.
+    #[serde(default)]
+    pub build_default_branch: bool,
+    /// Other branches built whenever a delegate pushes to them, e.g. `release/1.x`.
+    #[serde(default)]
+    pub build_branches: Vec<String>,
+    /// Path, relative to the repository root, of the pipeline configuration used to build tags
+    /// pushed by delegates. Tags are not built when not set.
+    pub release_pipeline_config: Option<String>,
+    /// Cron expression, in UTC, of scheduled builds of the default branch, e.g. `0 2 * * *` for
+    /// nightly builds.
+    pub schedule: Option<String>,
+    /// Builds draft patches too. Only patches that are ready for review are built when not set.
+    #[serde(default)]
+    pub build_drafts: bool,
+    /// Builds patches merged onto the canonical head of the default branch instead of on their own,
+    /// so that breakage caused by changes merged in the meantime is caught.
+    #[serde(default)]
+    pub merge_builds: bool,
+    /// Templates of the messages builds of the repository are reported with.
+    #[serde(default)]
+    pub report_templates: ReportTemplates,
+}
+
+/// Templates of the status messages of a build, replacing the default message of each status when
+/// set. The placeholders `{{status}}`, `{{url}}`, `{{duration}}` and `{{jobs}}` are replaced with
+/// the status of the build, its URL, how long it ran for and the outcome of each of its jobs.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ReportTemplates {
+    pub queued: Option<String>,
+    pub starting: Option<String>,
+    pub passed: Option<String>,
+    pub failed: Option<String>,
+    pub cancelled: Option<String>,
+    pub errored: Option<String>,
+}
+
+impl BrokerConfig {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+
+        serde_json::from_str(&content)
+            .map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source })
+    }
+
+    /// Returns the repositories keyed by their canonical id, i.e. without the `rad:` prefix.
+    pub fn repositories(&self) -> impl Iterator<Item=(&str, &RepositoryConfig)> {
+        self.repositories.iter().map(|(rid, config)| (canonical_rid(rid), config))
+    }
+
+    pub fn repository(&self, rid: &str) -> Option<&RepositoryConfig> {
+        let rid = canonical_rid(rid);
+        self.repositories().find(|(id, _)| *id == rid).map(|(_, config)| config)
+    }
+}
+
+impl RepositoryConfig {
+    /// Returns true if pushes of delegates to `branch` are built.
+    pub fn builds_branch(&self, branch: &str, default_branch: &str) -> bool {
+        if branch == default_branch {
+            self.build_default_branch
+        } else {
+            self.build_branches.iter().any(|built| built == branch)
+        }
+    }
+}
+
+fn canonical_rid(rid: &str) -> &str {
+    rid.strip_prefix("rad:").unwrap_or(rid)
+}
+
+fn redact<S: Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("<redacted>")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::config::{BrokerConfig, ReportTemplates, ReporterConfig, RepositoryConfig};
+
+    #[test]
+    fn will_successfully_deserialize_an_empty_config() -> Result<(), serde_json::Error> {
+        let config = serde_json::from_str::<BrokerConfig>("{}")?;
+
+        assert!(config.repositories.is_empty());
+        assert_eq!(config.results_file, None);
+        assert_eq!(config.reporters, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn will_find_repository_with_or_without_rad_prefix() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "repositories": {
+                "rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5": {
+                    "concourse_team": "radicle",
+                    "ci_command_users": ["did:key:z6MksFqXN3Yhqk8pTJdUGLwATkRfQvwZXPqR2qMEhbS9wzpT"],
+                    "build_default_branch": true,
+                    "build_branches": ["release/1.x"],
+                    "release_pipeline_config": ".concourse/release.yaml",
+                    "schedule": "0 2 * * *",
+                    "build_drafts": true,
+                    "merge_builds": true,
+                    "report_templates": {
+                        "passed": "CI {{status}} in {{duration}}: {{url}}"
+                    }
+                },
+                "z2UcCU1LgMshWvXj6hXSDDrwB8q8M": {}
+            }
+        }"#;
+
+        let config = serde_json::from_str::<BrokerConfig>(json)?;
+
+        let radicle = config.repository("z3gqcJUoA1n9HaHKufZs5FCSGazv5").unwrap();
+        assert_eq!(radicle.concourse_team, Some(String::from("radicle")));
+        assert_eq!(radicle.ci_command_users, vec![String::from("did:key:z6MksFqXN3Yhqk8pTJdUGLwATkRfQvwZXPqR2qMEhbS9wzpT")]);
+        assert!(radicle.build_default_branch);
+        assert_eq!(radicle.build_branches, vec![String::from("release/1.x")]);
+        assert_eq!(radicle.release_pipeline_config.as_deref(), Some(".concourse/release.yaml"));
+        assert_eq!(radicle.schedule.as_deref(), Some("0 2 * * *"));
+        assert!(radicle.build_drafts);
+        assert!(radicle.merge_builds);
+        assert_eq!(radicle.report_templates.passed.as_deref(), Some("CI {{status}} in {{duration}}: {{url}}"));
+        assert_eq!(radicle.report_templates.failed, None);
+        let other = config.repository("rad:z2UcCU1LgMshWvXj6hXSDDrwB8q8M").unwrap();
+        assert_eq!(other.concourse_team, None);
+        assert!(other.ci_command_users.is_empty());
+        assert!(!other.build_default_branch);
+        assert!(other.build_branches.is_empty());
+        assert_eq!(other.release_pipeline_config, None);
+        assert!(!other.build_drafts);
+        assert!(!other.merge_builds);
+        assert_eq!(other.report_templates, ReportTemplates::default());
+        assert!(config.repository("z4V1sjrXqjvFdnCUbxPFqd5p4DtH5").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn will_build_the_default_branch_and_configured_branches() {
+        let config = RepositoryConfig {
+            build_default_branch: true,
+            build_branches: vec![String::from("release/1.x")],
+            ..Default::default()
+        };
+        let branches_only = RepositoryConfig { build_default_branch: false, ..config.clone() };
+
+        assert!(config.builds_branch("master", "master"));
+        assert!(config.builds_branch("release/1.x", "master"));
+        assert!(!config.builds_branch("feature/ci", "master"));
+        assert!(!branches_only.builds_branch("master", "master"));
+        assert!(branches_only.builds_branch("release/1.x", "master"));
+    }
+
+    #[test]
+    fn will_deserialize_reporters() -> Result<(), serde_json::Error> {
+        let json = r#"
+        {
+            "reporters": [
+                { "type": "patch_comment" },
+                { "type": "webhook", "url": "https://chat.example.com/hooks/ci", "secret_file": "/run/secrets/webhook" }
+            ]
+        }"#;
+
+        let config = serde_json::from_str::<BrokerConfig>(json)?;
+
+        assert_eq!(config.reporters, Some(vec![
+            ReporterConfig::PatchComment,
+            ReporterConfig::Webhook {
+                url: String::from("https://chat.example.com/hooks/ci"),
+                secret_file: PathBuf::from("/run/secrets/webhook"),
+                dead_letter_file: None,
+                max_attempts: None,
+            },
+        ]));
+        Ok(())
+    }
+}
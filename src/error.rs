@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+use hyper::StatusCode;
+
+use crate::concourse::ci::ConcourseTeam;
+use crate::concourse::error::ConcourseError;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Every failure the broker can run into. The variants are coarse categories so that reports and
+/// metrics can tell configuration mistakes apart from Concourse or storage failures.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error(transparent)]
+    Api(#[from] ApiError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Patch(#[from] PatchError),
+    #[error(transparent)]
+    Node(#[from] NodeError),
+    #[error(transparent)]
+    Report(#[from] ReportError),
+    #[error(transparent)]
+    Server(#[from] ServerError),
+}
+
+impl Error {
+    /// Wraps an error returned by the Concourse API. Credential failures are reported as
+    /// authentication errors, everything else as API errors.
+    pub fn concourse(context: impl Into<String>, error: ConcourseError) -> Self {
+        match error {
+            ConcourseError::Credentials(_) | ConcourseError::InvalidToken(_) => {
+                Error::Auth(AuthError::Token { context: context.into(), source: error })
+            }
+            ConcourseError::Response { status, .. } if status == StatusCode::UNAUTHORIZED => {
+                Error::Auth(AuthError::Token { context: context.into(), source: error })
+            }
+            _ => Error::Api(ApiError { context: context.into(), source: error }),
+        }
+    }
+
+    /// A short, stable name of the error category, suitable for metric labels and reports.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Error::Config(_) => "config",
+            Error::Auth(_) => "auth",
+            Error::Api(_) => "api",
+            Error::Storage(_) => "storage",
+            Error::Patch(_) => "patch",
+            Error::Node(_) => "node",
+            Error::Report(_) => "report",
+            Error::Server(_) => "server",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("could not read configuration file {path}: {source}")]
+    Read { path: PathBuf, source: std::io::Error },
+    #[error("could not parse configuration file {path}: {source}")]
+    Parse { path: PathBuf, source: serde_json::Error },
+    #[error("file {path} not found in commit {commit}")]
+    PipelineConfigNotFound { path: String, commit: String },
+    #[error("pipeline {0} has no jobs")]
+    NoPipelineJobs(String),
+    #[error("pipeline {pipeline} has no job named {job}")]
+    PipelineJobNotFound { pipeline: String, job: String },
+    #[error("invalid repository id {0}")]
+    InvalidRepositoryId(String),
+    #[error("invalid schedule {expression}: {reason}")]
+    Schedule { expression: String, reason: String },
+    #[error("invalid log filter {filter}: {reason}")]
+    LogFilter { filter: String, reason: String },
+    #[error("unknown log format {0}, expected text or json")]
+    LogFormat(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("{context}: {source}")]
+    Token { context: String, source: ConcourseError },
+    #[error("Concourse user {user} is not a member of team {team}")]
+    NotTeamMember { user: String, team: ConcourseTeam },
+    #[error("could not read Concourse secret file {path}: {source}")]
+    SecretFile { path: PathBuf, source: std::io::Error },
+    #[error("environment variable {0} is not set")]
+    SecretEnv(String),
+    #[error("could not run Concourse secret command {command}: {source}")]
+    SecretCommand { command: String, source: std::io::Error },
+    #[error("Concourse secret command {command} exited with {status}")]
+    SecretCommandFailed { command: String, status: ExitStatus },
+    #[error("HOME is not set, cannot locate .flyrc")]
+    NoHome,
+    #[error("could not read {path}: {source}")]
+    ReadFlyrc { path: PathBuf, source: std::io::Error },
+    #[error("could not parse {path}: {source}")]
+    ParseFlyrc { path: PathBuf, source: serde_yaml::Error },
+    #[error("no bearer token found for target {target} in {path}")]
+    NoFlyrcToken { target: String, path: PathBuf },
+}
+
+/// A Concourse API request failed after authentication succeeded.
+#[derive(Debug, thiserror::Error)]
+#[error("{context}: {source}")]
+pub struct ApiError {
+    pub context: String,
+    pub source: ConcourseError,
+}
+
+impl ApiError {
+    /// The HTTP status Concourse responded with, if the request got that far.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.source.status()
+    }
+
+    /// The errors Concourse reported in the response body, if any.
+    pub fn body(&self) -> Option<&[String]> {
+        match &self.source {
+            ConcourseError::Response { error, .. } => Some(&error.errors),
+            _ => None,
+        }
+    }
+}
+
+/// Reading from or writing to the local Radicle storage failed.
+#[derive(Debug, thiserror::Error)]
+#[error("{context}: {source}")]
+pub struct StorageError {
+    pub context: String,
+    pub source: BoxError,
+}
+
+impl StorageError {
+    pub fn new(context: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self { context: context.into(), source: source.into() }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    #[error("invalid patch id {0}")]
+    InvalidId(String),
+    #[error("patch {0} not found")]
+    NotFound(String),
+    #[error("revision {revision} of patch {patch} not found")]
+    RevisionNotFound { patch: String, revision: String },
+    #[error("could not comment on patch {patch}: {source}")]
+    Comment { patch: String, source: BoxError },
+}
+
+/// Delivering a build report to a webhook failed.
+#[derive(Debug, thiserror::Error)]
+pub enum ReportError {
+    #[error("could not read webhook secret file {path}: {source}")]
+    Secret { path: PathBuf, source: std::io::Error },
+    #[error("webhook {url} failed after {attempts} attempts: {reason}")]
+    Delivery { url: String, attempts: u32, reason: String },
+    #[error("could not write dead letter to {path}: {source}")]
+    DeadLetter { path: PathBuf, source: std::io::Error },
+    #[error("delivery thread of webhook {url} has stopped")]
+    Stopped { url: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{context}: {source}")]
+pub struct NodeError {
+    pub context: String,
+    pub source: BoxError,
+}
+
+impl NodeError {
+    pub fn new(context: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self { context: context.into(), source: source.into() }
+    }
+}
+
+/// The status server could not be started or stopped serving.
+#[derive(Debug, thiserror::Error)]
+#[error("{context}: {source}")]
+pub struct ServerError {
+    pub context: String,
+    pub source: BoxError,
+}
+
+impl ServerError {
+    pub fn new(context: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self { context: context.into(), source: source.into() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use crate::concourse::error::ConcourseError;
+    use crate::concourse::response_error::ResponseError;
+    use crate::error::{ConfigError, Error};
+
+    fn response_error(status: StatusCode) -> ConcourseError {
+        ConcourseError::Response {
+            status,
+            error: ResponseError { errors: vec![String::from("error")], warnings: None },
+        }
+    }
+
+    #[test]
+    fn will_categorise_concourse_response_errors_as_api_errors() {
+        let error = Error::concourse("Failed to unpause pipeline", response_error(StatusCode::NOT_FOUND));
+
+        assert_eq!(error.category(), "api");
+        match error {
+            Error::Api(error) => {
+                assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+                assert_eq!(error.body(), Some(&[String::from("error")][..]));
+            }
+            _ => panic!("expected api error"),
+        }
+    }
+
+    #[test]
+    fn will_categorise_unauthorized_and_credential_errors_as_auth_errors() {
+        let unauthorized = Error::concourse("Failed to get build", response_error(StatusCode::UNAUTHORIZED));
+        let credentials = Error::concourse("Failed to get build", ConcourseError::credentials("no password"));
+
+        assert_eq!(unauthorized.category(), "auth");
+        assert_eq!(credentials.category(), "auth");
+    }
+
+    #[test]
+    fn will_categorise_config_errors() {
+        let error = Error::from(ConfigError::NoPipelineJobs(String::from("heartwood-pipeline")));
+
+        assert_eq!(error.category(), "config");
+        assert_eq!(error.to_string(), "pipeline heartwood-pipeline has no jobs");
+    }
+}
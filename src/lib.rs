@@ -1,5 +1,24 @@
+pub mod badge;
+pub mod builds;
 pub mod ci;
+pub mod cob;
+pub mod command;
 pub mod concourse;
-pub mod worker;
+pub mod config;
+pub mod error;
+pub mod logging;
+pub mod merge;
+pub mod metrics;
+pub mod monitor;
 pub mod pool;
+pub mod report;
+pub mod results;
 pub mod runtime;
+pub mod schedule;
+pub mod scheduler;
+pub mod server;
+pub mod status;
+pub mod template;
+pub mod worker;
+#[cfg(test)]
+mod test_support;
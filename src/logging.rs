@@ -0,0 +1,72 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use tracing_subscriber::EnvFilter;
+
+use crate::error::ConfigError;
+
+/// How log lines are written to standard output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// Human readable lines, prefixed with the fields of the enclosing spans.
+    #[default]
+    Text,
+    /// A JSON object per line, carrying the fields of the event and of the enclosing spans.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ConfigError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(ConfigError::LogFormat(format.to_string())),
+        }
+    }
+}
+
+impl Display for LogFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFormat::Text => f.write_str("text"),
+            LogFormat::Json => f.write_str("json"),
+        }
+    }
+}
+
+/// Parses a filter of the levels logged, e.g. `debug` or `info,radicle_ci::concourse=debug`.
+pub fn parse_filter(filter: &str) -> Result<EnvFilter, ConfigError> {
+    EnvFilter::try_new(filter)
+        .map_err(|error| ConfigError::LogFilter { filter: filter.to_string(), reason: error.to_string() })
+}
+
+/// Installs the global subscriber every log line of the broker goes through.
+pub fn init(filter: EnvFilter, format: LogFormat) {
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter).with_thread_names(true);
+
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().with_current_span(true).with_span_list(true).init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::{parse_filter, LogFormat};
+
+    #[test]
+    fn will_parse_log_formats() {
+        assert_eq!("text".parse::<LogFormat>().unwrap(), LogFormat::Text);
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("yaml".parse::<LogFormat>().unwrap_err().to_string(), "unknown log format yaml, expected text or json");
+    }
+
+    #[test]
+    fn will_parse_log_filters() {
+        assert!(parse_filter("debug").is_ok());
+        assert!(parse_filter("info,radicle_ci::concourse=debug").is_ok());
+        assert!(parse_filter("radicle_ci=loud").is_err());
+    }
+}
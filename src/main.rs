@@ -1,10 +1,18 @@
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process;
+use std::sync::Arc;
 
 use anyhow::anyhow;
 use radicle::profile::Profile;
-use radicle_term as term;
 use radicle_ci::ci::RadicleApiUrl;
-use radicle_ci::concourse::ci::ConcourseUrl;
+use radicle_ci::concourse::ci::{BuildLinks, ConcourseTeam, ConcourseUrl};
+use radicle_ci::concourse::auth::Authentication;
+use radicle_ci::concourse::credentials::{CONCOURSE_PASS_ENV, CONCOURSE_TOKEN_ENV, CredentialProvider, FlyrcToken, SecretCommand, SecretEnv, SecretFile};
+use radicle_ci::config::BrokerConfig;
+use radicle_ci::logging::{self, LogFormat};
+use tracing::{error, info};
 
 use radicle_ci::runtime::{CIConfig, Runtime};
 
@@ -15,19 +23,106 @@ Usage
 
 Options
 
-        --concourse-url      <url>          Concourse URL
-        --concourse-user     <user>         Concourse user
-        --concourse-pass     <pass>         Concourse password
-        --radicle-api-url    <url>          Radicle httpd API URL
+        --concourse-url          <url>      Concourse URL
+        --public-concourse-url   <url>      Concourse URL builds are linked to in reports
+                                            (default: --concourse-url)
+        --build-url-template     <template> Template of the URL builds are linked to in
+                                            reports, e.g. to link to a log viewer
+        --concourse-auth         <mode>     Authentication mode: password, token or
+                                            client-credentials (default: password)
+        --concourse-user         <user>     Concourse user (password mode)
+        --concourse-pass-file    <path>     File containing the Concourse password or
+                                            client secret
+        --concourse-pass-command <cmd>      Command printing the Concourse password or
+                                            client secret
+        --concourse-client-id    <id>       Client id (client-credentials mode)
+        --concourse-token-file   <path>     File containing a bearer token (token mode)
+        --concourse-flyrc-target <target>   Use the token `fly login` stored for this
+                                            target in ~/.flyrc (token mode)
+        --concourse-team         <team>     Concourse team (default: main)
+        --radicle-api-url        <url>      Radicle httpd API URL
+        --config                 <path>     Broker configuration file
+        --status-listen          <address>  Serve the broker status as JSON on this
+                                            address, e.g. 127.0.0.1:8090
+        --log-level              <filter>   Levels logged, e.g. debug or
+                                            info,radicle_ci::concourse=debug
+                                            (default: RUST_LOG or info)
+        --log-format             <format>   Log format: text or json (default: text)
         --help                              Print help
+
+The Concourse password or client secret is read from RADICLE_CI_CONCOURSE_PASS
+when neither --concourse-pass-file nor --concourse-pass-command is given. In
+token mode the token is read from RADICLE_CI_CONCOURSE_TOKEN when neither
+--concourse-token-file nor --concourse-flyrc-target is given.
+
+The build URL template may use the placeholders {{team}}, {{pipeline}}, {{job}},
+{{build}} and {{id}}, e.g. https://ci.example.com/builds/{{id}}. It takes
+precedence over --public-concourse-url.
 "#;
 
+#[derive(Debug)]
+enum SecretSource {
+    File(PathBuf),
+    Command(String),
+    Flyrc(String),
+    Env(&'static str),
+}
+
+impl SecretSource {
+    fn into_provider(self) -> Result<Arc<dyn CredentialProvider>, anyhow::Error> {
+        Ok(match self {
+            SecretSource::File(path) => Arc::new(SecretFile(path)),
+            SecretSource::Command(command) => Arc::new(SecretCommand(command)),
+            SecretSource::Flyrc(target) => Arc::new(FlyrcToken::from_home(target)?),
+            SecretSource::Env(name) => Arc::new(SecretEnv(String::from(name))),
+        })
+    }
+
+    fn or_env(source: Option<SecretSource>, name: &'static str, hint: &str) -> Result<SecretSource, anyhow::Error> {
+        match source {
+            Some(source) => Ok(source),
+            None if env::var_os(name).is_some() => Ok(SecretSource::Env(name)),
+            None => Err(anyhow!("missing {hint}")),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Options {
     concourse_url: String,
-    concourse_user: String,
-    concourse_pass: String,
+    build_links: BuildLinks,
+    concourse_auth: AuthOptions,
+    concourse_team: String,
     radicle_api_url: String,
+    config: Option<PathBuf>,
+    status_listen: Option<SocketAddr>,
+    log_level: String,
+    log_format: LogFormat,
+}
+
+#[derive(Debug)]
+enum AuthOptions {
+    Password { user: String, password: SecretSource },
+    Token { token: SecretSource },
+    ClientCredentials { client_id: String, client_secret: SecretSource },
+}
+
+impl AuthOptions {
+    fn into_authentication(self) -> Result<Authentication, anyhow::Error> {
+        Ok(match self {
+            AuthOptions::Password { user, password } => Authentication::Password {
+                user,
+                password: password.into_provider()?,
+            },
+            AuthOptions::Token { token } => Authentication::Bearer {
+                token: token.into_provider()?,
+            },
+            AuthOptions::ClientCredentials { client_id, client_secret } => Authentication::ClientCredentials {
+                client_id,
+                client_secret: client_secret.into_provider()?,
+            },
+        })
+    }
 }
 
 impl Options {
@@ -36,9 +131,19 @@ impl Options {
 
         let mut parser = lexopt::Parser::from_env();
         let mut concourse_url = None;
+        let mut public_concourse_url = None;
+        let mut build_url_template = None;
+        let mut concourse_auth = None;
         let mut concourse_user = None;
         let mut concourse_pass = None;
+        let mut concourse_client_id = None;
+        let mut concourse_token = None;
+        let mut concourse_team = None;
         let mut radicle_api_url = None;
+        let mut config = None;
+        let mut status_listen = None;
+        let mut log_level = None;
+        let mut log_format = None;
 
         while let Some(arg) = parser.next()? {
             match arg {
@@ -46,18 +151,66 @@ impl Options {
                     let x = parser.value()?.parse()?;
                     concourse_url = Some(x);
                 }
+                Long("public-concourse-url") => {
+                    let x = parser.value()?.parse()?;
+                    public_concourse_url = Some(x);
+                }
+                Long("build-url-template") => {
+                    let x = parser.value()?.parse()?;
+                    build_url_template = Some(x);
+                }
+                Long("concourse-auth") => {
+                    let x: String = parser.value()?.parse()?;
+                    concourse_auth = Some(x);
+                }
                 Long("concourse-user") => {
                     let x = parser.value()?.parse()?;
                     concourse_user = Some(x);
                 }
+                Long("concourse-pass-file") => {
+                    concourse_pass = Some(SecretSource::File(PathBuf::from(parser.value()?)));
+                }
+                Long("concourse-pass-command") => {
+                    let x = parser.value()?.parse()?;
+                    concourse_pass = Some(SecretSource::Command(x));
+                }
                 Long("concourse-pass") => {
+                    anyhow::bail!("--concourse-pass is not supported, use --concourse-pass-file, --concourse-pass-command or {CONCOURSE_PASS_ENV}");
+                }
+                Long("concourse-client-id") => {
+                    let x = parser.value()?.parse()?;
+                    concourse_client_id = Some(x);
+                }
+                Long("concourse-token-file") => {
+                    concourse_token = Some(SecretSource::File(PathBuf::from(parser.value()?)));
+                }
+                Long("concourse-flyrc-target") => {
+                    let x = parser.value()?.parse()?;
+                    concourse_token = Some(SecretSource::Flyrc(x));
+                }
+                Long("concourse-team") => {
                     let x = parser.value()?.parse()?;
-                    concourse_pass = Some(x);
+                    concourse_team = Some(x);
                 }
                 Long("radicle-api-url") => {
                     let x = parser.value()?.parse()?;
                     radicle_api_url = Some(x);
                 }
+                Long("config") => {
+                    config = Some(PathBuf::from(parser.value()?));
+                }
+                Long("status-listen") => {
+                    let x = parser.value()?.parse()?;
+                    status_listen = Some(x);
+                }
+                Long("log-level") => {
+                    let x = parser.value()?.parse()?;
+                    log_level = Some(x);
+                }
+                Long("log-format") => {
+                    let x = parser.value()?.parse()?;
+                    log_format = Some(x);
+                }
                 Long("help") | Short('h') => {
                     println!("{HELP_MSG}");
                     process::exit(0);
@@ -66,11 +219,39 @@ impl Options {
             }
         }
 
+        let secret_hint = format!("Concourse password, use --concourse-pass-file, --concourse-pass-command or {CONCOURSE_PASS_ENV}");
+        let concourse_auth = match concourse_auth.as_deref().unwrap_or("password") {
+            "password" => AuthOptions::Password {
+                user: concourse_user.ok_or(anyhow!("missing required option --concourse-user"))?,
+                password: SecretSource::or_env(concourse_pass, CONCOURSE_PASS_ENV, &secret_hint)?,
+            },
+            "token" => AuthOptions::Token {
+                token: SecretSource::or_env(concourse_token, CONCOURSE_TOKEN_ENV, &format!("Concourse token, use --concourse-token-file, --concourse-flyrc-target or {CONCOURSE_TOKEN_ENV}"))?,
+            },
+            "client-credentials" => AuthOptions::ClientCredentials {
+                client_id: concourse_client_id.ok_or(anyhow!("missing required option --concourse-client-id"))?,
+                client_secret: SecretSource::or_env(concourse_pass, CONCOURSE_PASS_ENV, &secret_hint)?,
+            },
+            mode => anyhow::bail!("unknown Concourse authentication mode {mode}"),
+        };
+
+        let concourse_url: String = concourse_url.ok_or(anyhow!("missing required option --concourse-url"))?;
+        let build_links = match (build_url_template, public_concourse_url) {
+            (Some(template), _) => BuildLinks::Template(template),
+            (None, Some(url)) => BuildLinks::Concourse(ConcourseUrl(url)),
+            (None, None) => BuildLinks::Concourse(ConcourseUrl(concourse_url.clone())),
+        };
+
         Ok(Self {
-            concourse_url: concourse_url.ok_or(anyhow!("missing required option --concourse-url"))?,
-            concourse_user: concourse_user.ok_or(anyhow!("missing required option --concourse_user"))?,
-            concourse_pass: concourse_pass.ok_or(anyhow!("missing required option --concourse_pass"))?,
+            concourse_url,
+            build_links,
+            concourse_auth,
+            concourse_team: concourse_team.unwrap_or(String::from("main")),
             radicle_api_url: radicle_api_url.ok_or(anyhow!("missing required option --radicle-api-url"))?,
+            config,
+            status_listen,
+            log_level: log_level.or_else(|| env::var("RUST_LOG").ok()).unwrap_or(String::from("info")),
+            log_format: log_format.unwrap_or_default(),
         })
     }
 }
@@ -82,25 +263,43 @@ fn profile() -> Result<Profile, anyhow::Error> {
     }
 }
 
-pub fn execute() -> anyhow::Result<()> {
+fn execute(options: Options) -> anyhow::Result<()> {
     let profile = profile()?;
-    let Options { concourse_url, concourse_user, concourse_pass, radicle_api_url } = Options::from_env()?;
+    let Options { concourse_url, build_links, concourse_auth, concourse_team, radicle_api_url, config, status_listen, .. } = options;
 
-    term::info!("Radicle CI init ...");
+    info!("Radicle CI init");
+    let broker_config = match config {
+        Some(path) => BrokerConfig::load(&path)?,
+        None => BrokerConfig::default(),
+    };
     let ci_config = CIConfig {
         concourse_url: ConcourseUrl(concourse_url),
-        ci_user: concourse_user,
-        ci_pass: concourse_pass,
+        build_links,
+        ci_auth: concourse_auth.into_authentication()?,
+        ci_team: ConcourseTeam(concourse_team),
     };
-    let runtime = Runtime::new(profile, RadicleApiUrl(radicle_api_url), ci_config);
+    let runtime = Runtime::new(profile, RadicleApiUrl(radicle_api_url), ci_config, broker_config, status_listen)?;
     runtime.run()?;
 
     Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
-    if let Err(err) = execute() {
-        term::info!("Fatal: {err}");
+    let options = Options::from_env().and_then(|options| {
+        logging::init(logging::parse_filter(&options.log_level)?, options.log_format);
+        Ok(options)
+    });
+    // Errors are printed as they are until the subscriber is installed.
+    let options = match options {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Fatal: {err}");
+            process::exit(1);
+        }
+    };
+
+    if let Err(err) = execute(options) {
+        error!(error = %err, "Fatal");
         process::exit(1);
     }
     Ok(())
@@ -0,0 +1,117 @@
+use git2::{Oid, Repository, Signature};
+
+/// The result of merging a patch onto the branch it targets.
+#[derive(Debug, PartialEq)]
+pub enum MergeOutcome {
+    Merged(Oid),
+    /// The paths that could not be merged.
+    Conflicts(Vec<String>),
+}
+
+/// The reference, in the broker's namespace, that keeps the merge commit of a patch reachable so
+/// that it can be fetched by the pipeline.
+pub fn merge_ref(namespace: &str, patch_id: &str) -> String {
+    format!("refs/namespaces/{namespace}/refs/heads/ci/merges/{patch_id}")
+}
+
+/// Deletes the merge reference of a patch that was merged or archived, so that its merge commit can
+/// be garbage collected. Returns false if the patch had no merge reference.
+pub fn remove_merge_ref(repository: &Repository, namespace: &str, patch_id: &str) -> Result<bool, git2::Error> {
+    match repository.find_reference(&merge_ref(namespace, patch_id)) {
+        Ok(mut reference) => reference.delete().map(|_| true),
+        Err(error) if error.code() == git2::ErrorCode::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Creates a commit merging `head` onto `base`, without updating any reference. Nothing is written
+/// when the merge has conflicts.
+pub fn merge_onto(repository: &Repository, base: Oid, head: Oid) -> Result<MergeOutcome, git2::Error> {
+    let base = repository.find_commit(base)?;
+    let head = repository.find_commit(head)?;
+    let mut index = repository.merge_commits(&base, &head, None)?;
+
+    if index.has_conflicts() {
+        let mut paths = index.conflicts()?
+            .filter_map(|conflict| conflict.ok())
+            .filter_map(|conflict| conflict.our.or(conflict.their).or(conflict.ancestor))
+            .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+            .collect::<Vec<_>>();
+        paths.dedup();
+        return Ok(MergeOutcome::Conflicts(paths));
+    }
+
+    let tree = repository.find_tree(index.write_tree_to(repository)?)?;
+    let signature = Signature::now("radicle-ci", "radicle-ci@localhost")?;
+    let message = format!("Merge {} onto {}", head.id(), base.id());
+    let commit = repository.commit(None, &signature, &signature, &message, &tree, &[&base, &head])?;
+
+    Ok(MergeOutcome::Merged(commit))
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{Oid, Repository, Signature};
+
+    use crate::merge::{merge_onto, merge_ref, remove_merge_ref, MergeOutcome};
+    use crate::test_support::TempPath;
+
+    fn commit(repository: &Repository, parent: Option<Oid>, files: &[(&str, &str)]) -> Oid {
+        let mut builder = repository.treebuilder(None).unwrap();
+        for (path, content) in files {
+            let blob = repository.blob(content.as_bytes()).unwrap();
+            builder.insert(path, blob, 0o100644).unwrap();
+        }
+        let tree = repository.find_tree(builder.write().unwrap()).unwrap();
+        let signature = Signature::now("alice", "alice@example.com").unwrap();
+        let parents = parent.map(|oid| repository.find_commit(oid).unwrap()).into_iter().collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+
+        repository.commit(None, &signature, &signature, "commit", &tree, &parents).unwrap()
+    }
+
+    fn repository(name: &str) -> (Repository, TempPath) {
+        let path = TempPath::new(&format!("merge-{name}"));
+        let repository = Repository::init_bare(&*path).unwrap();
+        (repository, path)
+    }
+
+    #[test]
+    fn will_merge_patch_onto_base() {
+        let (repository, _cleanup) = repository("clean");
+        let root = commit(&repository, None, &[("README", "hello")]);
+        let base = commit(&repository, Some(root), &[("README", "hello"), ("LICENSE", "MIT")]);
+        let head = commit(&repository, Some(root), &[("README", "hello"), ("main.rs", "fn main() {}")]);
+
+        let MergeOutcome::Merged(merge) = merge_onto(&repository, base, head).unwrap() else {
+            panic!("expected a clean merge");
+        };
+        let merge = repository.find_commit(merge).unwrap();
+        let tree = merge.tree().unwrap();
+
+        assert_eq!(merge.parent_ids().collect::<Vec<_>>(), vec![base, head]);
+        assert!(tree.get_name("LICENSE").is_some());
+        assert!(tree.get_name("main.rs").is_some());
+    }
+
+    #[test]
+    fn will_report_conflicting_paths() {
+        let (repository, _cleanup) = repository("conflict");
+        let root = commit(&repository, None, &[("README", "hello")]);
+        let base = commit(&repository, Some(root), &[("README", "hello, world")]);
+        let head = commit(&repository, Some(root), &[("README", "hello, radicle")]);
+
+        assert_eq!(merge_onto(&repository, base, head).unwrap(), MergeOutcome::Conflicts(vec![String::from("README")]));
+    }
+
+    #[test]
+    fn will_remove_the_merge_ref_of_a_patch() {
+        let (repository, _cleanup) = repository("remove");
+        let root = commit(&repository, None, &[("README", "hello")]);
+        repository.reference(&merge_ref("z6Mks", "f0b0c8"), root, true, "test").unwrap();
+
+        assert!(remove_merge_ref(&repository, "z6Mks", "f0b0c8").unwrap());
+        assert!(repository.find_reference(&merge_ref("z6Mks", "f0b0c8")).is_err());
+        assert!(!remove_merge_ref(&repository, "z6Mks", "f0b0c8").unwrap());
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::monitor::Monitor;
+
+/// Upper bounds, in seconds, of the build duration buckets.
+const BUILD_DURATION_BUCKETS: &[f64] = &[30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0, 7200.0];
+
+/// Upper bounds, in seconds, of the Concourse API latency buckets.
+const API_LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Why a worker did not build a job it was given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    /// The update does not need a build, e.g. a draft patch or a push to another branch.
+    NotNeeded,
+    /// The update was already built, or is being built.
+    Deduped,
+}
+
+impl SkipReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::NotNeeded => "not_needed",
+            SkipReason::Deduped => "deduped",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// The number of observations less than or equal to each bound.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self { bounds, buckets: vec![0; bounds.len()], sum: 0.0, count: 0 }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter_mut()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    events: BTreeMap<&'static str, u64>,
+    jobs: u64,
+    skipped_jobs: BTreeMap<SkipReason, u64>,
+    results: BTreeMap<String, u64>,
+    build_durations: BTreeMap<String, Histogram>,
+    api_latency: BTreeMap<&'static str, Histogram>,
+    api_errors: BTreeMap<&'static str, u64>,
+    token_refreshes: BTreeMap<&'static str, u64>,
+}
+
+/// Counters of what the broker did since it started, exposed in the Prometheus text format by the
+/// status server.
+#[derive(Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+/// The metrics of the broker, shared by everything that records them.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    /// A node event was received, e.g. `refs_fetched`.
+    pub fn event_received(&self, event_type: &'static str) {
+        *self.registry.lock().unwrap().events.entry(event_type).or_default() += 1;
+    }
+
+    /// A reference update was enqueued for the workers. Every job is counted once, whether it is
+    /// built or skipped.
+    pub fn job_enqueued(&self) {
+        self.registry.lock().unwrap().jobs += 1;
+    }
+
+    /// A worker did not build a job it was given.
+    pub fn job_skipped(&self, reason: SkipReason) {
+        *self.registry.lock().unwrap().skipped_jobs.entry(reason).or_default() += 1;
+    }
+
+    /// A build of the repository `rid` finished with `status`, after running for `duration` seconds
+    /// if it ran at all.
+    pub fn build_finished(&self, rid: &str, status: &str, duration: Option<u64>) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry.results.entry(status.to_string()).or_default() += 1;
+        if let Some(duration) = duration {
+            registry.build_durations.entry(rid.to_string())
+                .or_insert_with(|| Histogram::new(BUILD_DURATION_BUCKETS))
+                .observe(duration as f64);
+        }
+    }
+
+    /// A request to the Concourse API `endpoint` completed after `latency`. Requests that could not
+    /// be sent or were answered with an error status are counted as failed.
+    pub fn api_request(&self, endpoint: &'static str, latency: Duration, failed: bool) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.api_latency.entry(endpoint)
+            .or_insert_with(|| Histogram::new(API_LATENCY_BUCKETS))
+            .observe(latency.as_secs_f64());
+        if failed {
+            *registry.api_errors.entry(endpoint).or_default() += 1;
+        }
+    }
+
+    /// The Concourse access token was renewed through `grant`, e.g. `refresh_token`.
+    pub fn token_refreshed(&self, grant: &'static str) {
+        *self.registry.lock().unwrap().token_refreshes.entry(grant).or_default() += 1;
+    }
+
+    /// Renders the metrics, and the gauges of the monitor, in the Prometheus text format.
+    pub fn render(&self, monitor: &Monitor) -> String {
+        let registry = self.registry.lock().unwrap();
+        let busy_workers = monitor.workers().iter().filter(|worker| worker.busy_since.is_some()).count();
+        let mut output = String::new();
+
+        counter(&mut output, "radicle_ci_events_received_total", "Node events received, by type.", "type",
+                registry.events.iter().map(|(event, count)| (*event, *count)));
+        total(&mut output, "radicle_ci_jobs_total", "Reference updates enqueued for the workers.", registry.jobs);
+        counter(&mut output, "radicle_ci_jobs_skipped_total", "Enqueued reference updates that were not built, by reason.", "reason",
+                registry.skipped_jobs.iter().map(|(reason, count)| (reason.as_str(), *count)));
+        gauge(&mut output, "radicle_ci_queue_depth", "Jobs waiting for a worker.", monitor.queue().len());
+        gauge(&mut output, "radicle_ci_workers_busy", "Workers processing a job.", busy_workers);
+        histogram(&mut output, "radicle_ci_build_duration_seconds", "Duration of finished builds, by repository.", "rid",
+                  registry.build_durations.iter().map(|(rid, histogram)| (rid.as_str(), histogram)));
+        counter(&mut output, "radicle_ci_build_results_total", "Finished builds, by status.", "status",
+                registry.results.iter().map(|(status, count)| (status.as_str(), *count)));
+        histogram(&mut output, "radicle_ci_concourse_request_duration_seconds", "Latency of Concourse API requests, by endpoint.", "endpoint",
+                  registry.api_latency.iter().map(|(endpoint, histogram)| (*endpoint, histogram)));
+        counter(&mut output, "radicle_ci_concourse_request_errors_total", "Failed Concourse API requests, by endpoint.", "endpoint",
+                registry.api_errors.iter().map(|(endpoint, count)| (*endpoint, *count)));
+        counter(&mut output, "radicle_ci_concourse_token_refreshes_total", "Concourse access token renewals, by grant.", "grant",
+                registry.token_refreshes.iter().map(|(grant, count)| (*grant, *count)));
+
+        output
+    }
+}
+
+fn header(output: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(output, "# HELP {name} {help}");
+    let _ = writeln!(output, "# TYPE {name} {kind}");
+}
+
+fn counter<'a>(output: &mut String, name: &str, help: &str, label: &str, values: impl Iterator<Item=(&'a str, u64)>) {
+    header(output, name, help, "counter");
+    for (value, count) in values {
+        let _ = writeln!(output, "{name}{{{label}=\"{}\"}} {count}", escape(value));
+    }
+}
+
+/// A counter without labels.
+fn total(output: &mut String, name: &str, help: &str, value: u64) {
+    header(output, name, help, "counter");
+    let _ = writeln!(output, "{name} {value}");
+}
+
+fn gauge(output: &mut String, name: &str, help: &str, value: usize) {
+    header(output, name, help, "gauge");
+    let _ = writeln!(output, "{name} {value}");
+}
+
+fn histogram<'a>(output: &mut String, name: &str, help: &str, label: &str, values: impl Iterator<Item=(&'a str, &'a Histogram)>) {
+    header(output, name, help, "histogram");
+    for (value, histogram) in values {
+        let value = escape(value);
+        for (bound, bucket) in histogram.bounds.iter().zip(&histogram.buckets) {
+            let _ = writeln!(output, "{name}_bucket{{{label}=\"{value}\",le=\"{bound}\"}} {bucket}");
+        }
+        let _ = writeln!(output, "{name}_bucket{{{label}=\"{value}\",le=\"+Inf\"}} {}", histogram.count);
+        let _ = writeln!(output, "{name}_sum{{{label}=\"{value}\"}} {}", histogram.sum);
+        let _ = writeln!(output, "{name}_count{{{label}=\"{value}\"}} {}", histogram.count);
+    }
+}
+
+/// Escapes a label value, see https://prometheus.io/docs/instrumenting/exposition_formats/.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::metrics::{Metrics, SkipReason};
+    use crate::monitor::Monitor;
+
+    #[test]
+    fn will_render_counters_and_gauges() {
+        let metrics = Metrics::default();
+        metrics.event_received("refs_fetched");
+        metrics.event_received("refs_fetched");
+        metrics.job_enqueued();
+        metrics.job_skipped(SkipReason::Deduped);
+        metrics.token_refreshed("refresh_token");
+
+        let output = metrics.render(&Monitor::default());
+
+        assert!(output.contains("# TYPE radicle_ci_events_received_total counter\nradicle_ci_events_received_total{type=\"refs_fetched\"} 2\n"));
+        assert!(output.contains("# TYPE radicle_ci_jobs_total counter\nradicle_ci_jobs_total 1\n"));
+        assert!(output.contains("radicle_ci_jobs_skipped_total{reason=\"deduped\"} 1\n"));
+        assert!(output.contains("# TYPE radicle_ci_queue_depth gauge\nradicle_ci_queue_depth 0\n"));
+        assert!(output.contains("radicle_ci_workers_busy 0\n"));
+        assert!(output.contains("radicle_ci_concourse_token_refreshes_total{grant=\"refresh_token\"} 1\n"));
+    }
+
+    #[test]
+    fn will_render_cumulative_histograms() {
+        let metrics = Metrics::default();
+        metrics.build_finished("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "success", Some(45));
+        metrics.build_finished("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "failure", Some(700));
+        metrics.build_finished("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "conflicts", None);
+        metrics.api_request("get_build", Duration::from_millis(20), false);
+        metrics.api_request("get_build", Duration::from_millis(300), true);
+
+        let output = metrics.render(&Monitor::default());
+        let rid = "rid=\"z3gqcJUoA1n9HaHKufZs5FCSGazv5\"";
+
+        assert!(output.contains(&format!("radicle_ci_build_duration_seconds_bucket{{{rid},le=\"30\"}} 0\n")));
+        assert!(output.contains(&format!("radicle_ci_build_duration_seconds_bucket{{{rid},le=\"60\"}} 1\n")));
+        assert!(output.contains(&format!("radicle_ci_build_duration_seconds_bucket{{{rid},le=\"1200\"}} 2\n")));
+        assert!(output.contains(&format!("radicle_ci_build_duration_seconds_bucket{{{rid},le=\"+Inf\"}} 2\n")));
+        assert!(output.contains(&format!("radicle_ci_build_duration_seconds_sum{{{rid}}} 745\n")));
+        assert!(output.contains("radicle_ci_build_results_total{status=\"conflicts\"} 1\n"));
+        assert!(output.contains("radicle_ci_concourse_request_duration_seconds_bucket{endpoint=\"get_build\",le=\"0.025\"} 1\n"));
+        assert!(output.contains("radicle_ci_concourse_request_duration_seconds_count{endpoint=\"get_build\"} 2\n"));
+        assert!(output.contains("radicle_ci_concourse_request_errors_total{endpoint=\"get_build\"} 1\n"));
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::{SendError, Sender};
+use radicle::prelude::Id;
+use serde::Serialize;
+
+use crate::ci::CIBuild;
+use crate::error::Error;
+use crate::metrics::metrics;
+use crate::report::{BuildSubject, Outcome, Reporter};
+use crate::schedule::unix_now;
+use crate::worker::{Trigger, WorkerContext};
+
+/// How many finished builds are kept for the status API.
+const RECENT_RESULTS: usize = 100;
+
+/// A job enqueued for the workers.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Job {
+    pub rid: String,
+    pub trigger: Trigger,
+    /// Seconds since the Unix epoch.
+    pub enqueued_at: u64,
+}
+
+/// What a worker is doing, and how it fared so far.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WorkerHealth {
+    pub id: usize,
+    /// The job the worker is processing, if any.
+    pub job: Option<Job>,
+    /// The build the worker is watching, if it got that far.
+    pub build: Option<CIBuild>,
+    /// When the worker picked up its job, in seconds since the Unix epoch.
+    pub busy_since: Option<u64>,
+    pub processed: u64,
+    pub failed: u64,
+    pub last_error: Option<String>,
+}
+
+impl WorkerHealth {
+    fn new(id: usize) -> Self {
+        Self { id, job: None, build: None, busy_since: None, processed: 0, failed: 0, last_error: None }
+    }
+}
+
+/// The result of a finished build.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RecentResult {
+    pub rid: String,
+    pub patch_id: Option<String>,
+    pub revision_id: Option<String>,
+    pub head: String,
+    /// The status of the build, or `conflicts` if it was not run.
+    pub status: String,
+    pub url: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub finished_at: u64,
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Job>,
+    workers: BTreeMap<usize, WorkerHealth>,
+    recent: VecDeque<RecentResult>,
+}
+
+/// Keeps track of what the broker is doing, i.e. the jobs waiting for a worker, what every worker
+/// is busy with and the builds that finished recently, for the status API.
+#[derive(Clone, Default)]
+pub struct Monitor {
+    state: Arc<Mutex<State>>,
+}
+
+impl Monitor {
+    pub fn enqueued(&self, rid: &Id, trigger: &Trigger) {
+        let job = Job { rid: rid.canonical(), trigger: trigger.clone(), enqueued_at: unix_now() };
+        self.state.lock().unwrap().queue.push_back(job);
+    }
+
+    /// A worker picked up a job. Jobs are received in the order they were sent, so the first one
+    /// that is equal is the one picked up.
+    pub fn started(&self, worker: usize, rid: &Id, trigger: &Trigger) {
+        let mut state = self.state.lock().unwrap();
+        let rid = rid.canonical();
+        let position = state.queue.iter().position(|job| job.rid == rid && job.trigger == *trigger);
+        let job = position.and_then(|position| state.queue.remove(position));
+
+        let health = state.workers.entry(worker).or_insert_with(|| WorkerHealth::new(worker));
+        health.job = job;
+        health.build = None;
+        health.busy_since = Some(unix_now());
+    }
+
+    pub fn build_started(&self, worker: usize, build: &CIBuild) {
+        let mut state = self.state.lock().unwrap();
+        let health = state.workers.entry(worker).or_insert_with(|| WorkerHealth::new(worker));
+        health.build = Some(build.clone());
+    }
+
+    pub fn finished(&self, worker: usize, error: Option<&Error>) {
+        let mut state = self.state.lock().unwrap();
+        let health = state.workers.entry(worker).or_insert_with(|| WorkerHealth::new(worker));
+        health.job = None;
+        health.build = None;
+        health.busy_since = None;
+        health.processed += 1;
+        if let Some(error) = error {
+            health.failed += 1;
+            health.last_error = Some(error.to_string());
+        }
+    }
+
+    pub fn queue(&self) -> Vec<Job> {
+        self.state.lock().unwrap().queue.iter().cloned().collect()
+    }
+
+    pub fn workers(&self) -> Vec<WorkerHealth> {
+        self.state.lock().unwrap().workers.values().cloned().collect()
+    }
+
+    /// Returns the builds that finished recently, most recent first.
+    pub fn recent_results(&self) -> Vec<RecentResult> {
+        self.state.lock().unwrap().recent.iter().cloned().collect()
+    }
+
+    fn record(&self, result: RecentResult) {
+        let mut state = self.state.lock().unwrap();
+        state.recent.push_front(result);
+        state.recent.truncate(RECENT_RESULTS);
+    }
+}
+
+/// Keeps the results of finished builds, from every kind of build, for the status API.
+impl Reporter for Monitor {
+    fn on_queued(&self, _subject: &BuildSubject) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn on_started(&self, _subject: &BuildSubject, _build: &CIBuild) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn on_finished(&self, subject: &BuildSubject, outcome: &Outcome) -> Result<(), Error> {
+        let url = match outcome {
+            Outcome::Completed(result) => Some(result.url.clone()),
+            Outcome::Conflicts(_) => None,
+        };
+        self.record(RecentResult {
+            rid: subject.rid.canonical(),
+            patch_id: subject.patch_id.clone(),
+            revision_id: subject.revision_id.clone(),
+            head: subject.head.clone(),
+            status: String::from(outcome.status()),
+            url,
+            finished_at: unix_now(),
+        });
+
+        Ok(())
+    }
+}
+
+/// The sending end of the job queue, which keeps the monitor's view of the queue up to date.
+#[derive(Clone)]
+pub struct JobQueue {
+    sender: Sender<WorkerContext>,
+    monitor: Monitor,
+}
+
+impl JobQueue {
+    pub fn new(sender: Sender<WorkerContext>, monitor: Monitor) -> Self {
+        Self { sender, monitor }
+    }
+
+    pub fn send(&self, context: WorkerContext) -> Result<(), SendError<WorkerContext>> {
+        self.monitor.enqueued(context.rid(), context.trigger());
+        metrics().job_enqueued();
+        self.sender.send(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use radicle::prelude::Id;
+
+    use crate::ci::{CIBuild, CIResult, CIResultStatus};
+    use crate::error::{ConfigError, Error};
+    use crate::monitor::Monitor;
+    use crate::report::{BuildSubject, Outcome, Reporter};
+    use crate::worker::Trigger;
+
+    fn rid() -> Id {
+        Id::from_urn("rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5").unwrap()
+    }
+
+    fn patch(entry: &str) -> Trigger {
+        Trigger::Patch { patch_id: String::from("f0b0c8a4d3e2"), entry: String::from(entry) }
+    }
+
+    #[test]
+    fn will_track_jobs_from_the_queue_to_the_workers() {
+        let monitor = Monitor::default();
+        let build = CIBuild { id: 3094, url: String::from("http://localhost:8080/builds/3094") };
+
+        monitor.enqueued(&rid(), &patch("a1"));
+        monitor.enqueued(&rid(), &Trigger::Scheduled);
+        monitor.started(0, &rid(), &patch("a1"));
+        monitor.build_started(0, &build);
+
+        let queue = monitor.queue();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].trigger, Trigger::Scheduled);
+        let worker = &monitor.workers()[0];
+        assert_eq!(worker.job.as_ref().map(|job| &job.trigger), Some(&patch("a1")));
+        assert_eq!(worker.build, Some(build));
+
+        monitor.finished(0, Some(&Error::from(ConfigError::NoPipelineJobs(String::from("heartwood-pipeline")))));
+
+        let worker = &monitor.workers()[0];
+        assert_eq!(worker.job, None);
+        assert_eq!(worker.build, None);
+        assert_eq!((worker.processed, worker.failed), (1, 1));
+        assert_eq!(worker.last_error.as_deref(), Some("pipeline heartwood-pipeline has no jobs"));
+    }
+
+    #[test]
+    fn will_keep_the_most_recent_results_first() {
+        let monitor = Monitor::default();
+
+        for head in 0..150 {
+            let subject = BuildSubject { rid: rid(), patch_id: None, revision_id: None, head: head.to_string() };
+            let result = CIResult { status: CIResultStatus::Success, url: format!("http://ci/builds/{head}"), jobs: Vec::new(), duration: None };
+            monitor.on_finished(&subject, &Outcome::Completed(result)).unwrap();
+        }
+
+        let results = monitor.recent_results();
+        assert_eq!(results.len(), 100);
+        assert_eq!(results[0].head, "149");
+        assert_eq!(results[0].status, "success");
+        assert_eq!(results[99].head, "50");
+    }
+}
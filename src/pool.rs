@@ -2,25 +2,25 @@ use std::thread;
 use std::thread::JoinHandle;
 
 use crossbeam_channel::{Receiver, RecvError};
-use radicle_term as term;
+use tracing::{info, warn};
 
 use crate::ci::{CI};
-use crate::worker::{Worker, WorkerContext};
+use crate::worker::{Worker, WorkerContext, WorkerState};
 
 pub struct Pool {
     workers: Vec<JoinHandle<Result<(), RecvError>>>,
 }
 
 impl Pool {
-    pub fn with<T: 'static + CI + Send>(receiver: Receiver<WorkerContext>, handle: T) -> Self {
+    pub fn with<T: 'static + CI + Send>(receiver: Receiver<WorkerContext>, handle: T, state: WorkerState) -> Self {
         // TODO: Make capacity configurable
         let capacity = 5;
         let mut workers = Vec::with_capacity(capacity);
 
         for i in 0..capacity {
-            let mut worker = Worker::new(i, receiver.clone(), handle.clone());
+            let mut worker = Worker::new(i, receiver.clone(), handle.clone(), state.clone());
             let thread = thread::Builder::new().name(format!("worker-{i}")).spawn(move || {
-                term::info!("[{}] Worker {} started", i, worker.id);
+                info!(worker = worker.id, "Worker started");
                 worker.run()
             }).unwrap();
 
@@ -33,10 +33,10 @@ impl Pool {
     pub fn run(self) -> thread::Result<()> {
         for (i, worker) in self.workers.into_iter().enumerate() {
             if let Err(err) = worker.join()? {
-                term::info!("Worker {i} exited: {err}");
+                warn!(worker = i, error = %err, "Worker exited");
             }
         }
-        term::info!("Worker pool shutting down..");
+        info!("Worker pool shutting down");
 
         Ok(())
     }
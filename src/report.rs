@@ -0,0 +1,145 @@
+mod patch_comment;
+mod webhook;
+
+pub use patch_comment::PatchCommentReporter;
+pub use webhook::WebhookReporter;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use radicle::prelude::Id;
+use radicle::Profile;
+use tracing::warn;
+
+use crate::ci::{CIBuild, CIResult};
+use crate::config::{BrokerConfig, ReporterConfig};
+use crate::error::Error;
+
+/// What a build was run for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildSubject {
+    pub rid: Id,
+    /// The patch built, for patch builds.
+    pub patch_id: Option<String>,
+    /// The patch revision built, for patch builds.
+    pub revision_id: Option<String>,
+    /// The commit built.
+    pub head: String,
+}
+
+/// How a build ended.
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Completed(CIResult),
+    /// The build was not run because the patch does not merge cleanly, with the conflicting paths.
+    Conflicts(Vec<String>),
+}
+
+impl Outcome {
+    /// The status of the build, or `conflicts` if it was not run.
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Completed(result) => result.status.as_str(),
+            Outcome::Conflicts(_) => "conflicts",
+        }
+    }
+}
+
+/// Something build statuses are reported to, e.g. patch comments.
+pub trait Reporter: Send + Sync {
+    /// The build was picked up by a worker.
+    fn on_queued(&self, subject: &BuildSubject) -> Result<(), Error>;
+    /// The build was triggered in Concourse.
+    fn on_started(&self, subject: &BuildSubject, build: &CIBuild) -> Result<(), Error>;
+    fn on_finished(&self, subject: &BuildSubject, outcome: &Outcome) -> Result<(), Error>;
+}
+
+/// The reporters every build is reported to. A reporter that fails does not keep the others from
+/// reporting, nor fails the build.
+#[derive(Clone, Default)]
+pub struct Reporters {
+    reporters: Vec<Arc<dyn Reporter>>,
+}
+
+impl Reporters {
+    pub fn new(reporters: Vec<Arc<dyn Reporter>>) -> Self {
+        Self { reporters }
+    }
+
+    /// Creates the configured reporters. Builds are reported with patch comments when no reporters
+    /// are configured.
+    pub fn from_config(config: Arc<BrokerConfig>, profile: &Profile) -> Self {
+        let reporters = config.reporters.as_deref()
+            .unwrap_or(&[ReporterConfig::PatchComment])
+            .iter()
+            .map(|reporter| match reporter {
+                ReporterConfig::PatchComment => Arc::new(PatchCommentReporter::new(profile.clone(), config.clone())) as Arc<dyn Reporter>,
+                ReporterConfig::Webhook { url, secret_file, dead_letter_file, max_attempts } => match max_attempts {
+                    Some(attempts) => Arc::new(WebhookReporter::with_retries(url.clone(), secret_file.clone(), dead_letter_file.clone(), *attempts, Duration::from_secs(1))),
+                    None => Arc::new(WebhookReporter::new(url.clone(), secret_file.clone(), dead_letter_file.clone())),
+                },
+            })
+            .collect();
+
+        Self::new(reporters)
+    }
+
+    /// Adds a reporter next to the configured ones.
+    pub fn push(&mut self, reporter: Arc<dyn Reporter>) {
+        self.reporters.push(reporter);
+    }
+
+    pub fn on_queued(&self, subject: &BuildSubject) {
+        self.report(subject, |reporter| reporter.on_queued(subject));
+    }
+
+    pub fn on_started(&self, subject: &BuildSubject, build: &CIBuild) {
+        self.report(subject, |reporter| reporter.on_started(subject, build));
+    }
+
+    pub fn on_finished(&self, subject: &BuildSubject, outcome: &Outcome) {
+        self.report(subject, |reporter| reporter.on_finished(subject, outcome));
+    }
+
+    fn report(&self, subject: &BuildSubject, report: impl Fn(&dyn Reporter) -> Result<(), Error>) {
+        for reporter in &self.reporters {
+            if let Err(error) = report(reporter.as_ref()) {
+                warn!(head = %subject.head, %error, "Failed to report build");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use radicle::prelude::Id;
+
+    use crate::ci::{CIBuild, CIResult, CIResultStatus};
+    use crate::report::{BuildSubject, Outcome, Reporters};
+    use crate::test_support::Recorder;
+
+    #[test]
+    fn will_report_to_every_reporter_even_if_one_fails() {
+        let failing = Arc::new(Recorder::failing());
+        let recorder = Arc::new(Recorder::default());
+        let reporters = Reporters::new(vec![failing.clone(), recorder.clone()]);
+        let subject = BuildSubject {
+            rid: Id::from_urn("rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5").unwrap(),
+            patch_id: None,
+            revision_id: None,
+            head: String::from("d4e5f6"),
+        };
+        let build = CIBuild { id: 3094, url: String::from("http://localhost:8080/builds/3094") };
+        let result = CIResult { status: CIResultStatus::Success, url: build.url.clone(), jobs: Vec::new(), duration: None };
+
+        reporters.on_queued(&subject);
+        reporters.on_started(&subject, &build);
+        reporters.on_finished(&subject, &Outcome::Completed(result));
+
+        let expected = vec![String::from("queued d4e5f6"), String::from("started #3094"), String::from("finished Success")];
+        assert_eq!(failing.events(), expected);
+        assert_eq!(recorder.events(), expected);
+    }
+}
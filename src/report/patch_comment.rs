@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use radicle::cob::patch::{Patch, Patches, RevisionId};
+use radicle::cob::thread::CommentId;
+use radicle::crypto::PublicKey;
+use radicle::prelude::ReadStorage;
+use radicle::Profile;
+
+use crate::ci::CIBuild;
+use crate::config::{BrokerConfig, ReportTemplates};
+use crate::error::{Error, PatchError, StorageError};
+use crate::report::{BuildSubject, Outcome, Reporter};
+use crate::status::{RevisionStatus, StatusComment};
+
+/// Reports patch builds with a status comment on the revision built, using the report templates of
+/// the repository. Other builds have no patch to report to and are ignored.
+pub struct PatchCommentReporter {
+    profile: Profile,
+    config: Arc<BrokerConfig>,
+}
+
+impl PatchCommentReporter {
+    pub fn new(profile: Profile, config: Arc<BrokerConfig>) -> Self {
+        Self { profile, config }
+    }
+
+    fn set_status(&self, subject: &BuildSubject, status: RevisionStatus) -> Result<(), Error> {
+        let (Some(patch_id), Some(revision_id)) = (&subject.patch_id, &subject.revision_id) else {
+            return Ok(());
+        };
+        let rid = subject.rid;
+
+        let repository = self.profile.storage.repository(rid)
+            .map_err(|error| StorageError::new(format!("Failed to open repository {rid}"), error))?;
+        let mut patches = Patches::open(&repository)
+            .map_err(|error| StorageError::new(format!("Failed to open patches of repository {rid}"), error))?;
+        let id = patch_id.parse().map_err(|_| PatchError::InvalidId(patch_id.clone()))?;
+        let mut patch = patches.get_mut(&id).map_err(|_| PatchError::NotFound(patch_id.clone()))?;
+        let revision = patch.revisions()
+            .map(|(id, _)| id)
+            .find(|id| id.to_string() == *revision_id)
+            .ok_or_else(|| PatchError::RevisionNotFound { patch: patch_id.clone(), revision: revision_id.clone() })?;
+        let signer = self.profile.signer()
+            .map_err(|error| StorageError::new("Failed to load node signer", error))?;
+
+        let templates = self.config.repository(&rid.canonical()).map(|config| config.report_templates.clone()).unwrap_or_default();
+        let (comment, body) = next_status(&patch, &revision, &self.profile.public_key, status, &templates);
+        match comment {
+            Some(comment_id) => patch.comment_edit(revision, comment_id, body, &signer),
+            None => patch.comment(revision, body, None, &signer),
+        }.map_err(|error| PatchError::Comment { patch: patch_id.clone(), source: error.into() })?;
+
+        Ok(())
+    }
+}
+
+impl Reporter for PatchCommentReporter {
+    fn on_queued(&self, subject: &BuildSubject) -> Result<(), Error> {
+        self.set_status(subject, RevisionStatus::Queued)
+    }
+
+    fn on_started(&self, subject: &BuildSubject, build: &CIBuild) -> Result<(), Error> {
+        self.set_status(subject, RevisionStatus::Running { url: build.url.clone() })
+    }
+
+    fn on_finished(&self, subject: &BuildSubject, outcome: &Outcome) -> Result<(), Error> {
+        let status = match outcome {
+            Outcome::Completed(result) => RevisionStatus::Finished(result.clone()),
+            Outcome::Conflicts(paths) => RevisionStatus::Conflicts { paths: paths.clone() },
+        };
+        self.set_status(subject, status)
+    }
+}
+
+/// Returns the broker's status comment on a revision, if there is one yet, and the body it has to
+/// be updated to for the new status.
+fn next_status(patch: &Patch, revision_id: &RevisionId, broker: &PublicKey, status: RevisionStatus, templates: &ReportTemplates) -> (Option<CommentId>, String) {
+    let current = patch.revisions()
+        .filter(|(id, _)| id == revision_id)
+        .flat_map(|(_, revision)| revision.discussion().comments())
+        .find(|(_, comment)| comment.author() == *broker && comment.reply_to().is_none())
+        .map(|(comment_id, comment)| (*comment_id, comment.body().to_string()));
+    let body = StatusComment::next(current.as_ref().map(|(_, body)| body.as_str()), status).render(templates);
+
+    (current.map(|(comment_id, _)| comment_id), body)
+}
@@ -0,0 +1,299 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use hmac::{Hmac, Mac};
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use serde::Serialize;
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::ci::CIBuild;
+use crate::error::{Error, ReportError};
+use crate::report::{BuildSubject, Outcome, Reporter};
+use crate::schedule::unix_now;
+
+/// The header carrying the HMAC-SHA256 of the request body, as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Radicle-CI-Signature";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The body of a webhook request.
+#[derive(Debug, Serialize)]
+struct Payload {
+    event: &'static str,
+    rid: String,
+    patch_id: Option<String>,
+    revision_id: Option<String>,
+    head: String,
+    status: &'static str,
+    url: Option<String>,
+}
+
+impl Payload {
+    fn new(subject: &BuildSubject, event: &'static str, status: &'static str, url: Option<&str>) -> Self {
+        Self {
+            event,
+            rid: subject.rid.to_string(),
+            patch_id: subject.patch_id.clone(),
+            revision_id: subject.revision_id.clone(),
+            head: subject.head.clone(),
+            status,
+            url: url.map(String::from),
+        }
+    }
+}
+
+/// Posts every build status to an endpoint, signed with a secret shared with the receiver.
+/// Payloads are delivered in order by a thread of their own, so that a slow or unreachable
+/// endpoint does not hold up the worker.
+pub struct WebhookReporter {
+    url: String,
+    sender: Option<Sender<Payload>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WebhookReporter {
+    pub fn new(url: String, secret_file: PathBuf, dead_letter_file: Option<PathBuf>) -> Self {
+        Self::start(Delivery::new(url, secret_file, dead_letter_file))
+    }
+
+    /// Creates a reporter that attempts every delivery `attempts` times, waiting `backoff` before the
+    /// first retry and twice as long before each further one.
+    pub fn with_retries(url: String, secret_file: PathBuf, dead_letter_file: Option<PathBuf>, attempts: u32, backoff: Duration) -> Self {
+        Self::start(Delivery { attempts: attempts.max(1), backoff, ..Delivery::new(url, secret_file, dead_letter_file) })
+    }
+
+    fn start(delivery: Delivery) -> Self {
+        let url = delivery.url.clone();
+        let (sender, receiver) = crossbeam_channel::unbounded::<Payload>();
+        let thread = thread::Builder::new().name(String::from("webhook")).spawn(move || {
+            for payload in receiver {
+                if let Err(error) = delivery.send(&payload) {
+                    warn!(%error, "Failed to deliver webhook");
+                }
+            }
+        }).expect("webhook thread can be spawned");
+
+        Self { url, sender: Some(sender), thread: Some(thread) }
+    }
+
+    fn enqueue(&self, payload: Payload) -> Result<(), Error> {
+        self.sender.as_ref()
+            .and_then(|sender| sender.send(payload).ok())
+            .ok_or_else(|| ReportError::Stopped { url: self.url.clone() }.into())
+    }
+}
+
+/// Delivers the payloads that are still queued before the reporter goes away.
+impl Drop for WebhookReporter {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Reporter for WebhookReporter {
+    fn on_queued(&self, subject: &BuildSubject) -> Result<(), Error> {
+        self.enqueue(Payload::new(subject, "queued", "queued", None))
+    }
+
+    fn on_started(&self, subject: &BuildSubject, build: &CIBuild) -> Result<(), Error> {
+        self.enqueue(Payload::new(subject, "started", "running", Some(&build.url)))
+    }
+
+    fn on_finished(&self, subject: &BuildSubject, outcome: &Outcome) -> Result<(), Error> {
+        let payload = match outcome {
+            Outcome::Completed(result) => Payload::new(subject, "finished", result.status.as_str(), Some(&result.url)),
+            Outcome::Conflicts(_) => Payload::new(subject, "finished", "conflicts", None),
+        };
+        self.enqueue(payload)
+    }
+}
+
+/// Posts payloads to the endpoint. Failed deliveries are retried with exponential backoff, and
+/// appended to the dead letter file once the last attempt failed.
+struct Delivery {
+    runtime: tokio::runtime::Runtime,
+    client: Client<HttpsConnector<HttpConnector>>,
+    url: String,
+    secret_file: PathBuf,
+    dead_letter_file: Option<PathBuf>,
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl Delivery {
+    fn new(url: String, secret_file: PathBuf, dead_letter_file: Option<PathBuf>) -> Self {
+        Self {
+            runtime: tokio::runtime::Runtime::new().unwrap(),
+            client: Client::builder().build::<_, Body>(HttpsConnector::new()),
+            url,
+            secret_file,
+            dead_letter_file,
+            attempts: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+
+    fn send(&self, payload: &Payload) -> Result<(), Error> {
+        let body = serde_json::to_vec(payload).expect("payload is serializable");
+        let secret = fs::read(&self.secret_file)
+            .map_err(|source| ReportError::Secret { path: self.secret_file.clone(), source })?;
+        let signature = sign(secret.strip_suffix(b"\n").unwrap_or(&secret), &body);
+
+        let mut reason = String::new();
+        for attempt in 1..=self.attempts {
+            if attempt > 1 {
+                thread::sleep(self.backoff * 2u32.pow(attempt - 2));
+            }
+            match self.runtime.block_on(self.post(&body, &signature)) {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    warn!(url = %self.url, attempt, attempts = self.attempts, %error, "Webhook delivery failed");
+                    reason = error;
+                }
+            }
+        }
+
+        self.dead_letter(payload, &reason)?;
+        Err(ReportError::Delivery { url: self.url.clone(), attempts: self.attempts, reason }.into())
+    }
+
+    async fn post(&self, body: &[u8], signature: &str) -> Result<(), String> {
+        let request = Request::post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, format!("sha256={signature}"))
+            .body(Body::from(body.to_vec()))
+            .map_err(|error| error.to_string())?;
+
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(request)).await
+            .map_err(|_| String::from("request timed out"))?
+            .map_err(|error| error.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("endpoint responded with {}", response.status()));
+        }
+
+        Ok(())
+    }
+
+    /// Appends an undeliverable payload to the dead letter file as a line of JSON, so that it can
+    /// be inspected or replayed later.
+    fn dead_letter(&self, payload: &Payload, reason: &str) -> Result<(), Error> {
+        let Some(path) = &self.dead_letter_file else {
+            return Ok(());
+        };
+        let entry = serde_json::json!({
+            "timestamp": unix_now(),
+            "url": self.url,
+            "reason": reason,
+            "payload": payload,
+        });
+
+        OpenOptions::new().create(true).append(true).open(path)
+            .and_then(|mut file| writeln!(file, "{entry}"))
+            .map_err(|source| ReportError::DeadLetter { path: path.clone(), source })?;
+
+        Ok(())
+    }
+}
+
+/// Returns the hex encoded HMAC-SHA256 of a body.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(body);
+
+    mac.finalize().into_bytes().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use radicle::prelude::Id;
+
+    use crate::ci::{CIResult, CIResultStatus};
+    use crate::error::{Error, ReportError};
+    use crate::report::webhook::{sign, Delivery, Payload, WebhookReporter, SIGNATURE_HEADER};
+    use crate::report::{BuildSubject, Outcome, Reporter};
+    use crate::test_support::{stand_in, TempPath};
+
+    fn subject() -> BuildSubject {
+        BuildSubject {
+            rid: Id::from_urn("rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5").unwrap(),
+            patch_id: Some(String::from("f0b0c8a4d3e2")),
+            revision_id: Some(String::from("a1b2c3")),
+            head: String::from("d4e5f6"),
+        }
+    }
+
+    #[test]
+    fn will_sign_body_with_hmac_sha256() {
+        // Test case 2 of RFC 4231.
+        assert_eq!(sign(b"Jefe", b"what do ya want for nothing?"), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn will_retry_and_sign_deliveries() {
+        let (url, received) = stand_in(vec![(500, "")]);
+        let secret = TempPath::with_content("webhook-secret", "s3cr3t\n");
+        let reporter = WebhookReporter::with_retries(format!("{url}/hooks/ci"), secret.to_path_buf(), None, 3, Duration::from_millis(1));
+        let result = CIResult { status: CIResultStatus::Failure, url: String::from("http://localhost:8080/builds/3094"), jobs: Vec::new(), duration: Some(42) };
+
+        reporter.on_finished(&subject(), &Outcome::Completed(result)).unwrap();
+        // Dropping the reporter waits for the queued deliveries.
+        drop(reporter);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].path, "/hooks/ci");
+        let body = &received[1].body;
+        assert_eq!(received[1].headers[SIGNATURE_HEADER], format!("sha256={}", sign(b"s3cr3t", body.as_bytes())));
+        let payload = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(payload["event"], "finished");
+        assert_eq!(payload["rid"], "rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5");
+        assert_eq!(payload["patch_id"], "f0b0c8a4d3e2");
+        assert_eq!(payload["revision_id"], "a1b2c3");
+        assert_eq!(payload["head"], "d4e5f6");
+        assert_eq!(payload["status"], "failure");
+        assert_eq!(payload["url"], "http://localhost:8080/builds/3094");
+    }
+
+    #[test]
+    fn will_write_dead_letter_after_the_last_attempt() {
+        let (url, received) = stand_in(vec![(500, ""), (502, ""), (503, "")]);
+        let secret = TempPath::with_content("webhook-dead-letter-secret", "s3cr3t");
+        let dead_letters = TempPath::new("webhook-dead-letters");
+        let delivery = Delivery { attempts: 3, backoff: Duration::from_millis(1), ..Delivery::new(url, secret.to_path_buf(), Some(dead_letters.to_path_buf())) };
+
+        let result = delivery.send(&Payload::new(&subject(), "queued", "queued", None));
+
+        assert!(matches!(result, Err(Error::Report(ReportError::Delivery { attempts: 3, .. }))));
+        assert_eq!(received.lock().unwrap().len(), 3);
+        let content = fs::read_to_string(&dead_letters).unwrap();
+        let entry = serde_json::from_str::<serde_json::Value>(content.trim_end()).unwrap();
+        assert_eq!(entry["reason"], "endpoint responded with 503 Service Unavailable");
+        assert_eq!(entry["payload"]["status"], "queued");
+    }
+
+    #[test]
+    fn will_not_wait_for_the_endpoint() {
+        let secret = TempPath::with_content("webhook-unreachable-secret", "s3cr3t");
+        // Nothing listens on the discard port, every attempt fails after a backoff.
+        let reporter = WebhookReporter::with_retries(String::from("http://127.0.0.1:9/hooks/ci"), secret.to_path_buf(), None, 3, Duration::from_millis(200));
+        let started = std::time::Instant::now();
+
+        reporter.on_queued(&subject()).unwrap();
+
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+}
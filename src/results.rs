@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ci::{CIResult, CIResultStatus};
+use crate::error::StorageError;
+use crate::schedule::unix_now;
+
+/// The outcome of a branch or release build. These builds have no patch to comment on, so their
+/// results are kept by the broker instead.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BuildResult {
+    pub commit: String,
+    pub status: CIResultStatus,
+    pub url: String,
+    /// Seconds since the Unix epoch.
+    pub finished_at: u64,
+}
+
+impl BuildResult {
+    pub fn new(commit: String, result: CIResult) -> Self {
+        Self { commit, status: result.status, url: result.url, finished_at: unix_now() }
+    }
+}
+
+/// The latest result of a release build, as served by the status API.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReleaseResult {
+    pub rid: String,
+    pub tag: String,
+    #[serde(flatten)]
+    pub result: BuildResult,
+}
+
+/// The latest results of a repository, keyed by branch and tag name respectively.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct RepositoryResults {
+    #[serde(default)]
+    branches: HashMap<String, BuildResult>,
+    #[serde(default)]
+    releases: HashMap<String, BuildResult>,
+    /// When the last scheduled build was enqueued, in seconds since the Unix epoch.
+    last_scheduled_run: Option<u64>,
+    /// The default branch of the repository, as of its latest build.
+    default_branch: Option<String>,
+}
+
+type Results = HashMap<String, RepositoryResults>;
+
+/// The latest result of every built branch and release, keyed by repository id. When backed by a
+/// file, every recorded result is written to it so results survive a restart.
+#[derive(Clone, Default)]
+pub struct BuildResults {
+    path: Option<PathBuf>,
+    results: Arc<Mutex<Results>>,
+}
+
+impl BuildResults {
+    /// Loads the results stored in the file, which does not have to exist yet.
+    pub fn load(path: &Path) -> Result<Self, StorageError> {
+        let results = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|error| StorageError::new(format!("Failed to parse build results {}", path.display()), error))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Results::default(),
+            Err(error) => return Err(StorageError::new(format!("Failed to read build results {}", path.display()), error)),
+        };
+
+        Ok(Self { path: Some(path.to_path_buf()), results: Arc::new(Mutex::new(results)) })
+    }
+
+    pub fn record_branch(&self, rid: &str, branch: &str, result: BuildResult) -> Result<(), StorageError> {
+        self.record(rid, |results| {
+            results.branches.insert(branch.to_string(), result);
+        })
+    }
+
+    /// Records the result of a build of the default branch, which is the branch status badges show
+    /// unless another one is asked for.
+    pub fn record_default_branch(&self, rid: &str, branch: &str, result: BuildResult) -> Result<(), StorageError> {
+        self.record(rid, |results| {
+            results.branches.insert(branch.to_string(), result);
+            results.default_branch = Some(branch.to_string());
+        })
+    }
+
+    pub fn record_release(&self, rid: &str, tag: &str, result: BuildResult) -> Result<(), StorageError> {
+        self.record(rid, |results| {
+            results.releases.insert(tag.to_string(), result);
+        })
+    }
+
+    pub fn record_scheduled_run(&self, rid: &str, timestamp: u64) -> Result<(), StorageError> {
+        self.record(rid, |results| results.last_scheduled_run = Some(timestamp))
+    }
+
+    pub fn branch(&self, rid: &str, branch: &str) -> Option<BuildResult> {
+        self.results.lock().unwrap().get(rid)?.branches.get(branch).cloned()
+    }
+
+    /// Returns the latest result of the default branch, if it was ever built.
+    pub fn default_branch(&self, rid: &str) -> Option<BuildResult> {
+        let results = self.results.lock().unwrap();
+        let results = results.get(rid)?;
+
+        results.branches.get(results.default_branch.as_ref()?).cloned()
+    }
+
+    /// Returns the latest result of every built tag, of every repository or only of `rid`, most
+    /// recently finished first.
+    pub fn releases(&self, rid: Option<&str>) -> Vec<ReleaseResult> {
+        let mut releases = self.results.lock().unwrap()
+            .iter()
+            .filter(|(id, _)| rid.is_none() || rid == Some(id.as_str()))
+            .flat_map(|(id, results)| results.releases.iter().map(|(tag, result)| ReleaseResult {
+                rid: id.clone(),
+                tag: tag.clone(),
+                result: result.clone(),
+            }))
+            .collect::<Vec<_>>();
+        releases.sort_by(|a, b| b.result.finished_at.cmp(&a.result.finished_at).then_with(|| a.tag.cmp(&b.tag)));
+
+        releases
+    }
+
+    pub fn last_scheduled_run(&self, rid: &str) -> Option<u64> {
+        self.results.lock().unwrap().get(rid)?.last_scheduled_run
+    }
+
+    fn record(&self, rid: &str, update: impl FnOnce(&mut RepositoryResults)) -> Result<(), StorageError> {
+        let mut results = self.results.lock().unwrap();
+        update(results.entry(rid.to_string()).or_default());
+
+        match &self.path {
+            Some(path) => {
+                let content = serde_json::to_string_pretty(&*results)
+                    .map_err(|error| StorageError::new("Failed to serialize build results", error))?;
+                fs::write(path, content)
+                    .map_err(|error| StorageError::new(format!("Failed to write build results {}", path.display()), error))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ci::CIResultStatus;
+    use crate::error::StorageError;
+    use crate::results::{BuildResult, BuildResults, ReleaseResult};
+    use crate::test_support::TempPath;
+
+    const RID: &str = "z3gqcJUoA1n9HaHKufZs5FCSGazv5";
+
+    fn result(commit: &str, status: CIResultStatus) -> BuildResult {
+        BuildResult {
+            commit: String::from(commit),
+            status,
+            url: String::from("http://localhost:8080/teams/main/pipelines/heartwood/jobs/build/builds/1"),
+            finished_at: 1690735639,
+        }
+    }
+
+    #[test]
+    fn will_keep_the_latest_result_of_a_branch() {
+        let results = BuildResults::default();
+
+        results.record_branch(RID, "master", result("a1", CIResultStatus::Failure)).unwrap();
+        results.record_branch(RID, "master", result("b2", CIResultStatus::Success)).unwrap();
+
+        assert_eq!(results.branch(RID, "master"), Some(result("b2", CIResultStatus::Success)));
+        assert_eq!(results.branch(RID, "main"), None);
+    }
+
+    #[test]
+    fn will_keep_the_result_of_the_default_branch() {
+        let results = BuildResults::default();
+
+        results.record_branch(RID, "feature", result("a1", CIResultStatus::Success)).unwrap();
+        assert_eq!(results.default_branch(RID), None);
+
+        results.record_default_branch(RID, "master", result("b2", CIResultStatus::Failure)).unwrap();
+        assert_eq!(results.default_branch(RID), Some(result("b2", CIResultStatus::Failure)));
+        assert_eq!(results.branch(RID, "master"), Some(result("b2", CIResultStatus::Failure)));
+    }
+
+    #[test]
+    fn will_keep_release_results_apart_from_branch_results() {
+        let results = BuildResults::default();
+        let mut newer = result("b2", CIResultStatus::Failure);
+        newer.finished_at += 60;
+
+        results.record_release(RID, "v1.0.0", result("a1", CIResultStatus::Success)).unwrap();
+        results.record_release(RID, "v1.1.0", newer.clone()).unwrap();
+        results.record_release("z4V1sjrXqjvFdnCUbxPFqd5p4DtH5", "v0.1.0", result("c3", CIResultStatus::Success)).unwrap();
+
+        let releases = results.releases(Some(RID));
+        assert_eq!(releases, vec![
+            ReleaseResult { rid: String::from(RID), tag: String::from("v1.1.0"), result: newer },
+            ReleaseResult { rid: String::from(RID), tag: String::from("v1.0.0"), result: result("a1", CIResultStatus::Success) },
+        ]);
+        assert_eq!(results.releases(None).len(), 3);
+        assert_eq!(results.branch(RID, "v1.0.0"), None);
+    }
+
+    #[test]
+    fn will_persist_results_to_file() -> Result<(), StorageError> {
+        let path = TempPath::new("results.json");
+
+        let results = BuildResults::load(&path)?;
+        results.record_branch(RID, "master", result("a1", CIResultStatus::Success))?;
+        results.record_scheduled_run(RID, 1690848000)?;
+        let reloaded = BuildResults::load(&path)?;
+
+        assert_eq!(reloaded.branch(RID, "master"), Some(result("a1", CIResultStatus::Success)));
+        assert_eq!(reloaded.last_scheduled_run(RID), Some(1690848000));
+        Ok(())
+    }
+}
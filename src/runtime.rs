@@ -1,74 +1,153 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::{thread, time};
 
-use crossbeam_channel::Sender;
 use radicle::node::{Event, Handle};
+use radicle::prelude::Id;
 use radicle::Profile;
 use radicle::storage::RefUpdate;
-use radicle_term as term;
+use tracing::{debug, info, warn};
 use crate::ci::RadicleApiUrl;
 
+use crate::concourse::auth::Authentication;
 use crate::concourse::ci;
-use crate::concourse::ci::{ConcourseUrl};
+use crate::concourse::ci::{BuildLinks, ConcourseTeam, ConcourseTeams, ConcourseUrl};
+use crate::config::BrokerConfig;
+use crate::error::{ConfigError, Error, NodeError};
+use crate::metrics::metrics;
+use crate::monitor::{JobQueue, Monitor};
 use crate::pool::Pool;
-use crate::worker::WorkerContext;
+use crate::report::Reporters;
+use crate::results::BuildResults;
+use crate::schedule::CronSchedule;
+use crate::scheduler::Scheduler;
+use crate::server::StatusServer;
+use crate::worker::{WorkerContext, WorkerState};
 
 // TODO: Capture SIGINT and SIGTERM to gracefully shutdown
 
 pub struct CIConfig {
     pub concourse_url: ConcourseUrl,
-    pub ci_user: String,
-    pub ci_pass: String,
+    /// How builds are linked to in reports.
+    pub build_links: BuildLinks,
+    pub ci_auth: Authentication,
+    pub ci_team: ConcourseTeam,
 }
 
 pub struct Runtime {
     #[allow(dead_code)]
     pool: Pool,
     profile: Profile,
-    sender: Sender<WorkerContext>,
+    sender: JobQueue,
+    scheduler: Option<Scheduler>,
+    server: Option<(SocketAddr, StatusServer)>,
 }
 
 impl Runtime {
-    pub fn new(profile: Profile, radicle_api_url: RadicleApiUrl, ci_config: CIConfig) -> Self {
+    pub fn new(profile: Profile, radicle_api_url: RadicleApiUrl, ci_config: CIConfig, broker_config: BrokerConfig, status_address: Option<SocketAddr>) -> Result<Self, Error> {
         let (sender, receiver) = crossbeam_channel::unbounded::<WorkerContext>();
-        let handle = ci::ConcourseCI::new(radicle_api_url, ci_config.concourse_url, ci_config.ci_user, ci_config.ci_pass);
+        let monitor = Monitor::default();
+        let sender = JobQueue::new(sender, monitor.clone());
+        let teams = ConcourseTeams {
+            default: ci_config.ci_team,
+            repositories: broker_config.repositories()
+                .filter_map(|(rid, config)| {
+                    config.concourse_team.clone().map(|team| (rid.to_string(), ConcourseTeam(team)))
+                })
+                .collect(),
+        };
+        let mut handle = ci::ConcourseCI::new(radicle_api_url, ci_config.concourse_url, ci_config.build_links, ci_config.ci_auth, teams);
 
-        Runtime {
-            pool: Pool::with(receiver, handle),
+        info!("Validating Concourse team membership");
+        handle.validate_team_membership()?;
+
+        let results = match &broker_config.results_file {
+            Some(path) => BuildResults::load(path)?,
+            None => BuildResults::default(),
+        };
+        let schedules = broker_config.repositories()
+            .filter_map(|(rid, config)| config.schedule.as_deref().map(|schedule| (rid, schedule)))
+            .map(|(rid, schedule)| {
+                let rid = Id::from_urn(&format!("rad:{rid}"))
+                    .map_err(|_| ConfigError::InvalidRepositoryId(rid.to_string()))?;
+                Ok((rid, CronSchedule::parse(schedule)?))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let scheduler = (!schedules.is_empty())
+            .then(|| Scheduler::new(profile.clone(), sender.clone(), schedules, results.clone()));
+        let config = Arc::new(broker_config);
+        let mut reporters = Reporters::from_config(config.clone(), &profile);
+        reporters.push(Arc::new(monitor.clone()));
+        let server = status_address.map(|address| (address, StatusServer::new(monitor.clone(), config.clone(), results.clone())));
+        let state = WorkerState {
+            running: Default::default(),
+            config,
+            results,
+            reporters,
+            monitor,
+        };
+
+        Ok(Runtime {
+            pool: Pool::with(receiver, handle, state),
             profile,
             sender,
-        }
+            scheduler,
+            server,
+        })
     }
 
-    pub fn run(self) -> Result<(), anyhow::Error> {
+    pub fn run(mut self) -> Result<(), Error> {
+        if let Some(scheduler) = self.scheduler.take() {
+            thread::Builder::new().name(String::from("scheduler")).spawn(move || scheduler.run())
+                .map_err(|error| NodeError::new("Failed to spawn scheduler thread", error))?;
+        }
+
+        if let Some((address, server)) = self.server.take() {
+            thread::Builder::new().name(String::from("status-server")).spawn(move || {
+                if let Err(error) = server.run(address) {
+                    warn!(%error, "Status server stopped");
+                }
+            }).map_err(|error| NodeError::new("Failed to spawn status server thread", error))?;
+        }
+
         let t = thread::Builder::new().name(String::from("node-events")).spawn(move || {
             self.subscribe_to_node_events(self.profile.clone(), self.sender.clone())
-        })?;
+        }).map_err(|error| NodeError::new("Failed to spawn node events thread", error))?;
         t.join().unwrap()?;
         Ok(())
     }
 
-    fn subscribe_to_node_events(&self, profile: Profile, sender: Sender<WorkerContext>) -> anyhow::Result<()> {
-        term::info!("Subscribing to node events ...");
+    fn subscribe_to_node_events(&self, profile: Profile, sender: JobQueue) -> Result<(), Error> {
+        info!("Subscribing to node events");
         let node = radicle::Node::new(profile.socket());
-        let events = node.subscribe(time::Duration::MAX)?;
+        let events = node.subscribe(time::Duration::MAX)
+            .map_err(|error| NodeError::new("Failed to subscribe to node events", error))?;
 
         for event in events {
-            let event = event?;
+            let event = event.map_err(|error| NodeError::new("Failed to receive node event", error))?;
 
-            term::info!("Received event {:?}", event);
+            let event_name = event_type(&event);
+            debug!(event = event_name, "Received node event");
+            metrics().event_received(event_name);
 
             if let Event::RefsFetched { remote: _, rid, updated } = event {
                 for refs in updated {
-                    match refs {
-                        RefUpdate::Updated { name, .. } | RefUpdate::Created { name, .. } => {
-                            term::info!("Update reference announcement received: {name}");
-                            if name.contains("xyz.radicle.patch") {
-                                let patch_id = name.split('/').last().unwrap();
-                                // TODO: Handle channel send error
-                                let _ = sender.send(WorkerContext::new(rid, String::from(patch_id), profile.clone()));
-                            }
-                        }
-                        _ => (),
+                    let (name, head) = match refs {
+                        RefUpdate::Updated { name, new, .. } => (name, new),
+                        RefUpdate::Created { name, oid } => (name, oid),
+                        _ => continue,
+                    };
+                    info!(%rid, reference = %name, %head, "Reference updated");
+                    // TODO: Handle channel send error
+                    if name.contains("xyz.radicle.patch") {
+                        let patch_id = name.split('/').last().unwrap();
+                        let _ = sender.send(WorkerContext::new(rid, String::from(patch_id), head.to_string(), profile.clone()));
+                    } else if let Some((remote, branch)) = parse_namespaced_ref(&name, "refs/heads/") {
+                        let context = WorkerContext::push(rid, remote.to_string(), branch.to_string(), head.to_string(), profile.clone());
+                        let _ = sender.send(context);
+                    } else if let Some((remote, tag)) = parse_namespaced_ref(&name, "refs/tags/") {
+                        let context = WorkerContext::tag(rid, remote.to_string(), tag.to_string(), head.to_string(), profile.clone());
+                        let _ = sender.send(context);
                     }
                 }
             }
@@ -78,3 +157,67 @@ impl Runtime {
 }
 
 
+
+/// Returns the label node events are counted under, e.g. `refs_fetched`.
+fn event_type(event: &Event) -> &'static str {
+    match event {
+        Event::RefsFetched { .. } => "refs_fetched",
+        Event::RefsSynced { .. } => "refs_synced",
+        Event::SeedDiscovered { .. } => "seed_discovered",
+        Event::SeedDropped { .. } => "seed_dropped",
+        Event::PeerConnected { .. } => "peer_connected",
+        Event::PeerDisconnected { .. } => "peer_disconnected",
+    }
+}
+
+/// Splits a namespaced reference, e.g. `refs/namespaces/<nid>/refs/heads/<branch>`, into the
+/// namespace and the name following the `category` prefix, e.g. `refs/heads/`.
+fn parse_namespaced_ref<'a>(name: &'a str, category: &str) -> Option<(&'a str, &'a str)> {
+    let (remote, name) = name.strip_prefix("refs/namespaces/")?.split_once('/')?;
+    let name = name.strip_prefix(category)?;
+
+    Some((remote, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use radicle::node::Event;
+    use radicle::prelude::Id;
+
+    use crate::runtime::{event_type, parse_namespaced_ref};
+
+    const NAMESPACE: &str = "z6MksFqXN3Yhqk8pTJdUGLwATkRfQvwZXPqR2qMEhbS9wzpT";
+
+    #[test]
+    fn will_parse_namespaced_branch_refs() {
+        let name = format!("refs/namespaces/{NAMESPACE}/refs/heads/feature/ci");
+
+        assert_eq!(parse_namespaced_ref(&name, "refs/heads/"), Some((NAMESPACE, "feature/ci")));
+        assert_eq!(parse_namespaced_ref(&name, "refs/tags/"), None);
+    }
+
+    #[test]
+    fn will_parse_namespaced_tag_refs() {
+        let name = format!("refs/namespaces/{NAMESPACE}/refs/tags/v1.0.0");
+
+        assert_eq!(parse_namespaced_ref(&name, "refs/tags/"), Some((NAMESPACE, "v1.0.0")));
+    }
+
+    #[test]
+    fn will_label_event_types() {
+        let nid = NAMESPACE.parse().unwrap();
+        let rid = Id::from_urn("rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5").unwrap();
+
+        assert_eq!(event_type(&Event::RefsFetched { remote: nid, rid, updated: Vec::new() }), "refs_fetched");
+        assert_eq!(event_type(&Event::SeedDiscovered { rid, nid }), "seed_discovered");
+        assert_eq!(event_type(&Event::PeerConnected { nid }), "peer_connected");
+    }
+
+    #[test]
+    fn will_not_parse_refs_outside_of_the_category() {
+        let name = format!("refs/namespaces/{NAMESPACE}/refs/cobs/xyz.radicle.patch/f0b0c8a4");
+
+        assert_eq!(parse_namespaced_ref(&name, "refs/heads/"), None);
+        assert_eq!(parse_namespaced_ref("refs/heads/master", "refs/heads/"), None);
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::ConfigError;
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+/// How far ahead to look for the next run before giving up on a schedule that never fires, such as
+/// one for the 31st of February.
+const MAX_LOOKAHEAD: u64 = 5 * 366 * DAY;
+
+/// A cron schedule in the standard five field format, `minute hour day-of-month month day-of-week`,
+/// evaluated in UTC. Fields accept `*`, values, ranges, lists and steps, e.g. `0 2 * * 1-5` or
+/// `*/15 * * * *`. The `@hourly`, `@daily`, `@weekly` and `@monthly` shorthands are accepted too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// As in cron, a day matches either restricted day field when both are restricted.
+    any_day: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, ConfigError> {
+        let error = |reason: String| ConfigError::Schedule { expression: expression.to_string(), reason };
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(error(format!("expected 5 fields, found {}", fields.len())));
+        };
+
+        let mut days_of_week_bits = parse_field(days_of_week, 0, 7).map_err(error)?;
+        // Both 0 and 7 stand for Sunday.
+        if days_of_week_bits & (1 << 7) != 0 {
+            days_of_week_bits = (days_of_week_bits | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59).map_err(error)?,
+            hours: parse_field(hours, 0, 23).map_err(error)?,
+            days_of_month: parse_field(days_of_month, 1, 31).map_err(error)?,
+            months: parse_field(months, 1, 12).map_err(error)?,
+            days_of_week: days_of_week_bits,
+            any_day: days_of_month.starts_with('*') || days_of_week.starts_with('*'),
+        })
+    }
+
+    /// Returns the first time, in seconds since the Unix epoch, strictly after `timestamp` the
+    /// schedule fires at.
+    pub fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let mut time = (timestamp / MINUTE + 1) * MINUTE;
+        let limit = time + MAX_LOOKAHEAD;
+
+        while time < limit {
+            let days = time / DAY;
+            if !self.matches_day(days) {
+                time = (days + 1) * DAY;
+                continue;
+            }
+            if !contains(self.hours, (time % DAY) / HOUR) {
+                time = (time / HOUR + 1) * HOUR;
+                continue;
+            }
+            if !contains(self.minutes, (time % HOUR) / MINUTE) {
+                time += MINUTE;
+                continue;
+            }
+            return Some(time);
+        }
+
+        None
+    }
+
+    /// Returns true if the schedule should have fired between the last run and now, i.e. a run was
+    /// missed while the broker was down.
+    pub fn has_missed_run(&self, last_run: u64, now: u64) -> bool {
+        self.next_after(last_run).is_some_and(|due| due <= now)
+    }
+
+    fn matches_day(&self, days_since_epoch: u64) -> bool {
+        let (month, day) = month_and_day(days_since_epoch);
+        // The Unix epoch was a Thursday.
+        let weekday = (days_since_epoch + 4) % 7;
+        let day_of_month = contains(self.days_of_month, day);
+        let day_of_week = contains(self.days_of_week, weekday);
+
+        contains(self.months, month) && if self.any_day {
+            day_of_month && day_of_week
+        } else {
+            day_of_month || day_of_week
+        }
+    }
+}
+
+/// The current time in seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn contains(bits: u64, value: u64) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let number = |value: &str| value.parse::<u64>().map_err(|_| format!("invalid value {value} in field {field}"));
+    let mut bits = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, number(step)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // `5/10` means every 10 starting at 5.
+            None if part.contains('/') => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+
+        if step == 0 || start < min || end > max || start > end {
+            return Err(format!("{part} is out of range {min}-{max}"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+/// Converts days since the Unix epoch to the month and day of month of the Gregorian calendar.
+fn month_and_day(days_since_epoch: u64) -> (u64, u64) {
+    // Shift the epoch to 0000-03-01 so that leap days fall at the end of the year.
+    let days = days_since_epoch + 719_468;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+
+    (if month < 10 { month + 3 } else { month - 9 }, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schedule::CronSchedule;
+
+    // 2023-08-01T00:00:00Z, a Tuesday.
+    const AUGUST_1ST: u64 = 1_690_848_000;
+    const HOUR: u64 = 3600;
+    const DAY: u64 = 24 * HOUR;
+
+    #[test]
+    fn will_find_next_nightly_run() {
+        let schedule = CronSchedule::parse("0 2 * * *").unwrap();
+
+        assert_eq!(schedule.next_after(AUGUST_1ST), Some(AUGUST_1ST + 2 * HOUR));
+        assert_eq!(schedule.next_after(AUGUST_1ST + 2 * HOUR), Some(AUGUST_1ST + DAY + 2 * HOUR));
+    }
+
+    #[test]
+    fn will_support_steps_lists_and_ranges() {
+        let every_quarter = CronSchedule::parse("*/15 * * * *").unwrap();
+        let weekdays = CronSchedule::parse("30 1,13 * * 1-5").unwrap();
+
+        assert_eq!(every_quarter.next_after(AUGUST_1ST + 60), Some(AUGUST_1ST + 15 * 60));
+        // Friday the 4th at 13:30 is followed by Monday the 7th at 01:30.
+        assert_eq!(weekdays.next_after(AUGUST_1ST + 3 * DAY + 13 * HOUR + 1800), Some(AUGUST_1ST + 6 * DAY + HOUR + 1800));
+    }
+
+    #[test]
+    fn will_match_either_day_field_when_both_are_restricted() {
+        // The 15th of the month or any Sunday.
+        let schedule = CronSchedule::parse("0 0 15 * 0").unwrap();
+
+        assert_eq!(schedule.next_after(AUGUST_1ST), Some(AUGUST_1ST + 5 * DAY));
+        assert_eq!(schedule.next_after(AUGUST_1ST + 12 * DAY), Some(AUGUST_1ST + 14 * DAY));
+    }
+
+    #[test]
+    fn will_handle_month_boundaries_and_shorthands() {
+        let schedule = CronSchedule::parse("@monthly").unwrap();
+
+        assert_eq!(schedule.next_after(AUGUST_1ST), Some(AUGUST_1ST + 31 * DAY));
+        assert_eq!(CronSchedule::parse("@daily").unwrap(), CronSchedule::parse("0 0 * * *").unwrap());
+        assert_eq!(CronSchedule::parse("0 0 * * 7").unwrap(), CronSchedule::parse("0 0 * * 0").unwrap());
+    }
+
+    #[test]
+    fn will_not_find_a_run_for_impossible_dates() {
+        let schedule = CronSchedule::parse("0 0 31 2 *").unwrap();
+
+        assert_eq!(schedule.next_after(AUGUST_1ST), None);
+    }
+
+    #[test]
+    fn will_detect_missed_runs() {
+        let schedule = CronSchedule::parse("0 2 * * *").unwrap();
+
+        assert!(schedule.has_missed_run(AUGUST_1ST, AUGUST_1ST + 3 * DAY));
+        assert!(!schedule.has_missed_run(AUGUST_1ST + 2 * HOUR, AUGUST_1ST + DAY));
+    }
+
+    #[test]
+    fn will_reject_invalid_expressions() {
+        assert!(CronSchedule::parse("0 2 * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("0 night * * *").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use std::thread;
+use std::time::Duration;
+
+use radicle::prelude::Id;
+use radicle::Profile;
+use tracing::{info, warn};
+
+use crate::monitor::JobQueue;
+use crate::results::BuildResults;
+use crate::schedule::{CronSchedule, unix_now};
+use crate::worker::WorkerContext;
+
+/// The longest the scheduler sleeps, so that a changed system clock is picked up in time.
+const MAX_SLEEP: u64 = 60;
+
+/// Enqueues builds of the default branch of every repository with a schedule, next to the builds
+/// triggered by node events.
+pub struct Scheduler {
+    profile: Profile,
+    sender: JobQueue,
+    schedules: Vec<(Id, CronSchedule)>,
+    results: BuildResults,
+}
+
+impl Scheduler {
+    pub fn new(profile: Profile, sender: JobQueue, schedules: Vec<(Id, CronSchedule)>, results: BuildResults) -> Self {
+        Self { profile, sender, schedules, results }
+    }
+
+    pub fn run(self) {
+        let now = unix_now();
+
+        // Runs missed while the broker was down are caught up with a single build, however many
+        // were missed. Repositories that have never been built on schedule wait for the next run.
+        for (rid, schedule) in &self.schedules {
+            if let Some(last_run) = self.results.last_scheduled_run(&rid.canonical()) {
+                if schedule.has_missed_run(last_run, now) {
+                    info!(%rid, "Scheduled build was missed, running it now");
+                    self.enqueue(*rid, now);
+                }
+            }
+        }
+
+        let mut next_runs = self.schedules.iter()
+            .map(|(_, schedule)| schedule.next_after(now))
+            .collect::<Vec<_>>();
+
+        loop {
+            let now = unix_now();
+            for ((rid, schedule), next_run) in self.schedules.iter().zip(next_runs.iter_mut()) {
+                if next_run.is_some_and(|due| due <= now) {
+                    self.enqueue(*rid, now);
+                    *next_run = schedule.next_after(now);
+                }
+            }
+
+            let sleep = next_runs.iter()
+                .flatten()
+                .min()
+                .map_or(MAX_SLEEP, |due| due.saturating_sub(now).clamp(1, MAX_SLEEP));
+            thread::sleep(Duration::from_secs(sleep));
+        }
+    }
+
+    fn enqueue(&self, rid: Id, now: u64) {
+        info!(%rid, "Enqueueing scheduled build");
+        // TODO: Handle channel send error
+        let _ = self.sender.send(WorkerContext::scheduled(rid, self.profile.clone()));
+        if let Err(error) = self.results.record_scheduled_run(&rid.canonical(), now) {
+            warn!(%rid, %error, "Failed to record scheduled run");
+        }
+    }
+}
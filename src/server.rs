@@ -0,0 +1,364 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::badge;
+use crate::ci::CIBuild;
+use crate::config::BrokerConfig;
+use crate::error::{Error, ServerError};
+use crate::metrics::metrics;
+use crate::monitor::{Job, Monitor};
+use crate::results::{BuildResults, ReleaseResult};
+
+const ROUTES: &[&str] = &["/api/v1/queue", "/api/v1/builds", "/api/v1/results", "/api/v1/releases", "/api/v1/workers", "/api/v1/config", "/metrics"];
+
+/// The body of a response.
+#[derive(Debug)]
+enum Reply {
+    Json(StatusCode, Value),
+    /// Metrics in the Prometheus text format.
+    Metrics(String),
+    Badge(String),
+}
+
+/// A job a worker is processing, with the Concourse build it is watching once triggered.
+#[derive(Debug, Serialize)]
+struct RunningJob {
+    worker: usize,
+    job: Option<Job>,
+    build: Option<CIBuild>,
+    busy_since: Option<u64>,
+}
+
+/// Serves the state of the broker as JSON, for dashboards to poll:
+///
+/// - `GET /api/v1/queue`: the jobs waiting for a worker, oldest first.
+/// - `GET /api/v1/builds`: the jobs the workers are processing, with their builds.
+/// - `GET /api/v1/results`: the builds that finished recently, most recent first, optionally
+///   filtered with the `rid` and `patch` query parameters.
+/// - `GET /api/v1/releases`: the latest result of every built tag, most recent first, optionally
+///   filtered with the `rid` query parameter.
+/// - `GET /api/v1/workers`: what every worker is doing and how many jobs it failed.
+/// - `GET /api/v1/config`: the broker configuration.
+/// - `GET /metrics`: the metrics of the broker, in the Prometheus text format.
+/// - `GET /badge/<rid>.svg`: a status badge of the latest build of the default branch of a
+///   repository, and `GET /badge/<rid>/<branch>.svg` of another branch.
+#[derive(Clone)]
+pub struct StatusServer {
+    monitor: Monitor,
+    config: Arc<BrokerConfig>,
+    results: BuildResults,
+}
+
+impl StatusServer {
+    pub fn new(monitor: Monitor, config: Arc<BrokerConfig>, results: BuildResults) -> Self {
+        Self { monitor, config, results }
+    }
+
+    /// Serves requests on `address` until the server fails.
+    pub fn run(self, address: SocketAddr) -> Result<(), Error> {
+        let runtime = tokio::runtime::Runtime::new()
+            .map_err(|error| ServerError::new("Failed to start status server runtime", error))?;
+
+        runtime.block_on(async move {
+            let service = make_service_fn(move |_| {
+                let server = self.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let response = server.respond(&request);
+                        async move { Ok::<_, Infallible>(response) }
+                    }))
+                }
+            });
+            let server = Server::try_bind(&address)
+                .map_err(|error| ServerError::new(format!("Failed to listen on {address}"), error))?
+                .serve(service);
+
+            info!(address = %server.local_addr(), "Serving broker status");
+            server.await.map_err(|error| ServerError::new("Status server failed", error).into())
+        })
+    }
+
+    fn respond(&self, request: &Request<Body>) -> Response<Body> {
+        let (status, content_type, body) = match self.handle(request.method(), request.uri().path(), request.uri().query()) {
+            Reply::Json(status, body) => (status, "application/json", body.to_string()),
+            Reply::Metrics(body) => (StatusCode::OK, "text/plain; version=0.0.4", body),
+            Reply::Badge(body) => (StatusCode::OK, "image/svg+xml", body),
+        };
+
+        // Nothing is cached, badges embedded in cached pages must still show the latest result.
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, content_type)
+            .header(CACHE_CONTROL, "no-cache")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn handle(&self, method: &Method, path: &str, query: Option<&str>) -> Reply {
+        let badge = path.strip_prefix("/badge/").and_then(|badge| badge.strip_suffix(".svg"));
+
+        if !ROUTES.contains(&path) && badge.is_none() {
+            return Reply::Json(StatusCode::NOT_FOUND, json!({ "error": format!("{path} not found") }));
+        }
+        if method != Method::GET {
+            return Reply::Json(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": format!("{method} is not allowed on {path}") }));
+        }
+        if let Some(badge) = badge {
+            return Reply::Badge(self.badge(badge));
+        }
+
+        let body = match path {
+            "/metrics" => return Reply::Metrics(metrics().render(&self.monitor)),
+            "/api/v1/queue" => json!(self.monitor.queue()),
+            "/api/v1/builds" => json!(self.running_jobs()),
+            "/api/v1/results" => json!(self.results(query)),
+            "/api/v1/releases" => json!(self.releases(query)),
+            "/api/v1/workers" => json!(self.monitor.workers()),
+            _ => json!(self.config.as_ref()),
+        };
+
+        Reply::Json(StatusCode::OK, body)
+    }
+
+    fn running_jobs(&self) -> Vec<RunningJob> {
+        self.monitor.workers()
+            .into_iter()
+            .filter(|worker| worker.busy_since.is_some())
+            .map(|worker| RunningJob { worker: worker.id, job: worker.job, build: worker.build, busy_since: worker.busy_since })
+            .collect()
+    }
+
+    /// Renders the badge of `<rid>` or `<rid>/<branch>`. Repositories and branches that were never
+    /// built get an unknown badge rather than an error, so that pages embedding it are not broken.
+    fn badge(&self, badge: &str) -> String {
+        // Branch names are percent-encoded in the path, e.g. `feature%2Fci`, rids have no slashes.
+        let badge = percent_decode_str(badge).decode_utf8_lossy();
+        let (rid, branch) = match badge.split_once('/') {
+            Some((rid, branch)) => (rid, Some(branch)),
+            None => (badge.as_ref(), None),
+        };
+        let rid = rid.trim_start_matches("rad:");
+        let result = match branch {
+            Some(branch) => self.results.branch(rid, branch),
+            None => self.results.default_branch(rid),
+        };
+
+        badge::render(result.as_ref().map(|result| &result.status))
+    }
+
+    fn results(&self, query: Option<&str>) -> Vec<Value> {
+        let rid = parameter(query, "rid").map(|rid| rid.trim_start_matches("rad:").to_string());
+        let patch = parameter(query, "patch");
+
+        self.monitor.recent_results()
+            .into_iter()
+            .filter(|result| rid.is_none() || rid.as_ref() == Some(&result.rid))
+            .filter(|result| patch.is_none() || result.patch_id == patch)
+            .map(|result| json!(result))
+            .collect()
+    }
+
+    fn releases(&self, query: Option<&str>) -> Vec<ReleaseResult> {
+        let rid = parameter(query, "rid");
+
+        self.results.releases(rid.as_deref().map(|rid| rid.trim_start_matches("rad:")))
+    }
+}
+
+/// Returns the value of a query parameter, if it is given.
+fn parameter(query: Option<&str>, name: &str) -> Option<String> {
+    form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::{Method, StatusCode};
+    use radicle::prelude::Id;
+    use serde_json::{json, Value};
+
+    use crate::ci::{CIBuild, CIResult, CIResultStatus};
+    use crate::config::BrokerConfig;
+    use crate::monitor::Monitor;
+    use crate::report::{BuildSubject, Outcome, Reporter};
+    use crate::results::{BuildResult, BuildResults};
+    use crate::server::{Reply, StatusServer};
+    use crate::worker::Trigger;
+
+    const RID: &str = "z3gqcJUoA1n9HaHKufZs5FCSGazv5";
+
+    fn rid() -> Id {
+        Id::from_urn(&format!("rad:{RID}")).unwrap()
+    }
+
+    fn get(server: &StatusServer, path: &str, query: Option<&str>) -> (StatusCode, Value) {
+        match server.handle(&Method::GET, path, query) {
+            Reply::Json(status, body) => (status, body),
+            Reply::Metrics(_) | Reply::Badge(_) => panic!("expected a JSON reply"),
+        }
+    }
+
+    fn finish(monitor: &Monitor, patch_id: Option<&str>, head: &str) {
+        let subject = BuildSubject { rid: rid(), patch_id: patch_id.map(String::from), revision_id: None, head: String::from(head) };
+        let result = CIResult { status: CIResultStatus::Failure, url: String::from("http://ci/builds/1"), jobs: Vec::new(), duration: None };
+        monitor.on_finished(&subject, &Outcome::Completed(result)).unwrap();
+    }
+
+    #[test]
+    fn will_list_queued_and_running_jobs() {
+        let monitor = Monitor::default();
+        let server = StatusServer::new(monitor.clone(), Arc::new(BrokerConfig::default()), BuildResults::default());
+        let trigger = Trigger::Patch { patch_id: String::from("f0b0c8a4"), entry: String::from("a1") };
+
+        monitor.enqueued(&rid(), &trigger);
+        monitor.enqueued(&rid(), &Trigger::Scheduled);
+        monitor.started(2, &rid(), &trigger);
+        monitor.build_started(2, &CIBuild { id: 3094, url: String::from("http://ci/builds/3094") });
+
+        let (status, queue) = get(&server, "/api/v1/queue", None);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(queue[0]["rid"], json!(RID));
+        assert_eq!(queue[0]["trigger"], json!({ "type": "scheduled" }));
+
+        let (_, builds) = get(&server, "/api/v1/builds", None);
+        assert_eq!(builds.as_array().unwrap().len(), 1);
+        assert_eq!(builds[0]["worker"], json!(2));
+        assert_eq!(builds[0]["job"]["trigger"], json!({ "type": "patch", "patch_id": "f0b0c8a4", "entry": "a1" }));
+        assert_eq!(builds[0]["build"]["id"], json!(3094));
+    }
+
+    #[test]
+    fn will_filter_results_by_repository_and_patch() {
+        let monitor = Monitor::default();
+        let server = StatusServer::new(monitor.clone(), Arc::new(BrokerConfig::default()), BuildResults::default());
+
+        finish(&monitor, None, "c0ffee");
+        finish(&monitor, Some("f0b0c8a4"), "beef");
+
+        let (_, all) = get(&server, "/api/v1/results", Some(&format!("rid=rad%3A{RID}")));
+        let (_, patch) = get(&server, "/api/v1/results", Some("patch=f0b0c8a4"));
+        let (_, other) = get(&server, "/api/v1/results", Some("rid=z4V1sjrXqjvFdnCUbxPFqd5p4DtH5"));
+
+        assert_eq!(all.as_array().unwrap().len(), 2);
+        assert_eq!(patch.as_array().unwrap().len(), 1);
+        assert_eq!(patch[0]["head"], json!("beef"));
+        assert_eq!(patch[0]["status"], json!("failure"));
+        assert_eq!(other, json!([]));
+    }
+
+    #[test]
+    fn will_serve_release_results() {
+        let results = BuildResults::default();
+        let server = StatusServer::new(Monitor::default(), Arc::new(BrokerConfig::default()), results.clone());
+        let result = BuildResult { commit: String::from("a1"), status: CIResultStatus::Success, url: String::from("http://ci/builds/1"), finished_at: 1690735639 };
+
+        results.record_release(RID, "v1.0.0", result.clone()).unwrap();
+        results.record_release("z4V1sjrXqjvFdnCUbxPFqd5p4DtH5", "v0.1.0", result).unwrap();
+
+        let (status, releases) = get(&server, "/api/v1/releases", Some(&format!("rid=rad%3A{RID}")));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(releases, json!([{
+            "rid": RID,
+            "tag": "v1.0.0",
+            "commit": "a1",
+            "status": "success",
+            "url": "http://ci/builds/1",
+            "finished_at": 1690735639,
+        }]));
+        assert_eq!(get(&server, "/api/v1/releases", None).1.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn will_serve_the_configuration() -> Result<(), serde_json::Error> {
+        let config = serde_json::from_str::<BrokerConfig>(r#"{
+            "repositories": {
+                "rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5": { "merge_builds": true }
+            }
+        }"#)?;
+        let server = StatusServer::new(Monitor::default(), Arc::new(config), BuildResults::default());
+
+        let (status, config) = get(&server, "/api/v1/config", None);
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(config["repositories"][format!("rad:{RID}")]["merge_builds"], json!(true));
+        Ok(())
+    }
+
+    #[test]
+    fn will_redact_secrets_from_the_configuration() -> Result<(), serde_json::Error> {
+        let config = serde_json::from_str::<BrokerConfig>(r#"{
+            "reporters": [{
+                "type": "webhook",
+                "url": "https://chat.example.com/hooks/t0k3n",
+                "secret_file": "/run/secrets/webhook",
+                "dead_letter_file": "/var/lib/radicle-ci/dead-letters.jsonl"
+            }]
+        }"#)?;
+        let server = StatusServer::new(Monitor::default(), Arc::new(config), BuildResults::default());
+
+        let (_, config) = get(&server, "/api/v1/config", None);
+
+        let body = config.to_string();
+        assert!(!body.contains("t0k3n"));
+        assert!(!body.contains("/run/secrets/webhook"));
+        assert_eq!(config["reporters"][0]["url"], json!("<redacted>"));
+        assert!(config["reporters"][0].get("secret_file").is_none());
+        assert_eq!(config["reporters"][0]["dead_letter_file"], json!("/var/lib/radicle-ci/dead-letters.jsonl"));
+        Ok(())
+    }
+
+    #[test]
+    fn will_reject_unknown_routes_and_methods() {
+        let server = StatusServer::new(Monitor::default(), Arc::new(BrokerConfig::default()), BuildResults::default());
+
+        assert_eq!(get(&server, "/api/v1/unknown", None).0, StatusCode::NOT_FOUND);
+        assert!(matches!(server.handle(&Method::POST, "/api/v1/queue", None), Reply::Json(StatusCode::METHOD_NOT_ALLOWED, _)));
+    }
+
+    #[test]
+    fn will_serve_metrics_with_the_queue_depth() {
+        let monitor = Monitor::default();
+        let server = StatusServer::new(monitor.clone(), Arc::new(BrokerConfig::default()), BuildResults::default());
+        monitor.enqueued(&rid(), &Trigger::Scheduled);
+
+        let Reply::Metrics(metrics) = server.handle(&Method::GET, "/metrics", None) else {
+            panic!("expected metrics");
+        };
+
+        assert!(metrics.contains("radicle_ci_queue_depth 1\n"));
+    }
+
+    #[test]
+    fn will_serve_badges_of_the_default_and_other_branches() {
+        let results = BuildResults::default();
+        let server = StatusServer::new(Monitor::default(), Arc::new(BrokerConfig::default()), results.clone());
+        let result = |status| BuildResult { commit: String::from("a1"), status, url: String::from("http://ci/builds/1"), finished_at: 1690735639 };
+        let badge = |path: &str| match server.handle(&Method::GET, path, None) {
+            Reply::Badge(badge) => badge,
+            reply => panic!("expected a badge, got {reply:?}"),
+        };
+
+        results.record_default_branch(RID, "master", result(CIResultStatus::Success)).unwrap();
+        results.record_branch(RID, "feature/ci", result(CIResultStatus::Failure)).unwrap();
+
+        assert!(badge(&format!("/badge/{RID}.svg")).contains("<title>CI: passing</title>"));
+        assert!(badge(&format!("/badge/rad:{RID}.svg")).contains("<title>CI: passing</title>"));
+        assert!(badge(&format!("/badge/{RID}/feature/ci.svg")).contains("<title>CI: failing</title>"));
+        assert!(badge(&format!("/badge/{RID}/feature%2Fci.svg")).contains("<title>CI: failing</title>"));
+        assert!(badge(&format!("/badge/{RID}/release.svg")).contains("<title>CI: unknown</title>"));
+        assert!(badge("/badge/z4V1sjrXqjvFdnCUbxPFqd5p4DtH5.svg").contains("<title>CI: unknown</title>"));
+        assert_eq!(get(&server, &format!("/badge/{RID}.png"), None).0, StatusCode::NOT_FOUND);
+    }
+}
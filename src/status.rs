@@ -0,0 +1,201 @@
+use crate::ci::{CIResult, CIResultStatus};
+use crate::config::ReportTemplates;
+use crate::template::{format_duration, render};
+
+const HISTORY_HEADER: &str = "Previous builds:";
+
+const QUEUED: &str = "⏳ CI build is queued";
+const STARTING: &str = "🏃 CI build is running: {{url}}";
+const PASSED: &str = "🎉 CI build has PASSED: {{url}}";
+const FAILED: &str = "🙁 CI build has FAILED: {{url}}\n\n{{jobs}}";
+const CANCELLED: &str = "🛑 CI build was CANCELLED: {{url}}";
+const ERRORED: &str = "💥 CI build has ERRORED: {{url}}\n\n{{jobs}}";
+
+/// The CI status of a patch revision, as shown in the broker's status comment.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RevisionStatus {
+    Queued,
+    Running { url: String },
+    Finished(CIResult),
+    /// The revision was not built because it does not merge cleanly.
+    Conflicts { paths: Vec<String> },
+}
+
+impl RevisionStatus {
+    /// The message describing the status. Its first line is kept in the history once the revision
+    /// is built again.
+    fn message(&self, templates: &ReportTemplates) -> String {
+        match self {
+            RevisionStatus::Queued => render(templates.queued.as_deref().unwrap_or(QUEUED), &[("status", "QUEUED")]),
+            RevisionStatus::Running { url } => {
+                render(templates.starting.as_deref().unwrap_or(STARTING), &[("status", "RUNNING"), ("url", url)])
+            }
+            RevisionStatus::Finished(result) => {
+                let (template, default) = match result.status {
+                    CIResultStatus::Success => (&templates.passed, PASSED),
+                    CIResultStatus::Failure => (&templates.failed, FAILED),
+                    CIResultStatus::Aborted => (&templates.cancelled, CANCELLED),
+                    CIResultStatus::Errored => (&templates.errored, ERRORED),
+                };
+                let jobs = result.jobs.iter()
+                    .map(|job| format!("- {}: {}", job.name, label(&job.status)))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let values = [
+                    ("status", label(&result.status).to_uppercase()),
+                    ("url", result.url.clone()),
+                    ("duration", result.duration.map(format_duration).unwrap_or_default()),
+                    ("jobs", jobs),
+                ];
+                let values = values.iter().map(|(name, value)| (*name, value.as_str())).collect::<Vec<_>>();
+
+                render(template.as_deref().unwrap_or(default), &values).trim_end().to_string()
+            }
+            RevisionStatus::Conflicts { paths } => {
+                let files = paths.iter().map(|path| format!("\n- {path}")).collect::<String>();
+                format!("⚠️ CI build was not run, the patch does not merge cleanly into the default branch\n\nConflicting files:{files}")
+            }
+        }
+    }
+}
+
+fn label(status: &CIResultStatus) -> &'static str {
+    match status {
+        CIResultStatus::Success => "passed",
+        CIResultStatus::Failure => "failed",
+        CIResultStatus::Aborted => "cancelled",
+        CIResultStatus::Errored => "errored",
+    }
+}
+
+/// The single comment the broker keeps up to date on every revision it builds. It shows the status
+/// of the latest build, followed by the outcome of the builds before it, most recent first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusComment {
+    pub status: RevisionStatus,
+    pub history: Vec<String>,
+}
+
+impl StatusComment {
+    /// Moves the status comment to a new status. A newly queued build pushes the status of the
+    /// previous one into the history.
+    pub fn next(previous: Option<&str>, status: RevisionStatus) -> Self {
+        let Some(previous) = previous else {
+            return Self { status, history: Vec::new() };
+        };
+
+        let mut history = parse_history(previous);
+        if status == RevisionStatus::Queued {
+            if let Some(summary) = previous.lines().next().filter(|line| !line.trim().is_empty()) {
+                history.insert(0, summary.to_string());
+            }
+        }
+
+        Self { status, history }
+    }
+
+    pub fn render(&self, templates: &ReportTemplates) -> String {
+        let mut body = self.status.message(templates);
+
+        if !self.history.is_empty() {
+            body.push_str(&format!("\n\n{HISTORY_HEADER}"));
+            for entry in &self.history {
+                body.push_str(&format!("\n- {entry}"));
+            }
+        }
+
+        body
+    }
+}
+
+fn parse_history(body: &str) -> Vec<String> {
+    body.lines()
+        .skip_while(|line| *line != HISTORY_HEADER)
+        .skip(1)
+        .filter_map(|line| line.strip_prefix("- "))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ci::{CIResult, CIResultStatus, JobName, JobResult};
+    use crate::config::ReportTemplates;
+    use crate::status::{RevisionStatus, StatusComment};
+
+    const URL: &str = "http://localhost:8080/teams/main/pipelines/heartwood/jobs/build/builds/1";
+
+    fn finished(status: CIResultStatus, url: &str) -> RevisionStatus {
+        RevisionStatus::Finished(CIResult { status, url: String::from(url), jobs: Vec::new(), duration: Some(83) })
+    }
+
+    fn next(previous: Option<&str>, status: RevisionStatus) -> String {
+        StatusComment::next(previous, status).render(&ReportTemplates::default())
+    }
+
+    #[test]
+    fn will_transition_from_queued_to_finished() {
+        let queued = next(None, RevisionStatus::Queued);
+        let running = next(Some(&queued), RevisionStatus::Running { url: String::from(URL) });
+        let passed = next(Some(&running), finished(CIResultStatus::Success, URL));
+
+        assert_eq!(queued, "⏳ CI build is queued");
+        assert_eq!(running, format!("🏃 CI build is running: {URL}"));
+        assert_eq!(passed, format!("🎉 CI build has PASSED: {URL}"));
+    }
+
+    #[test]
+    fn will_keep_history_of_reruns() {
+        let failed = next(None, finished(CIResultStatus::Failure, "http://ci/builds/1"));
+        let requeued = next(Some(&failed), RevisionStatus::Queued);
+        let passed = next(Some(&requeued), finished(CIResultStatus::Success, "http://ci/builds/2"));
+        let queued_again = StatusComment::next(Some(&passed), RevisionStatus::Queued);
+
+        assert_eq!(passed, "🎉 CI build has PASSED: http://ci/builds/2\n\nPrevious builds:\n- 🙁 CI build has FAILED: http://ci/builds/1");
+        assert_eq!(queued_again.history, vec![
+            String::from("🎉 CI build has PASSED: http://ci/builds/2"),
+            String::from("🙁 CI build has FAILED: http://ci/builds/1"),
+        ]);
+    }
+
+    #[test]
+    fn will_list_conflicting_files_apart_from_history() {
+        let failed = next(None, finished(CIResultStatus::Failure, "http://ci/builds/1"));
+        let requeued = next(Some(&failed), RevisionStatus::Queued);
+        let conflicts = next(Some(&requeued), RevisionStatus::Conflicts { paths: vec![String::from("README")] });
+
+        assert_eq!(conflicts, "⚠️ CI build was not run, the patch does not merge cleanly into the default branch\n\nConflicting files:\n- README\n\nPrevious builds:\n- 🙁 CI build has FAILED: http://ci/builds/1");
+        assert_eq!(StatusComment::next(Some(&conflicts), RevisionStatus::Queued).history.len(), 2);
+    }
+
+    #[test]
+    fn will_list_jobs_of_failed_builds() {
+        let mut status = finished(CIResultStatus::Failure, URL);
+        if let RevisionStatus::Finished(result) = &mut status {
+            result.jobs = vec![
+                JobResult { name: JobName(String::from("build")), status: CIResultStatus::Success },
+                JobResult { name: JobName(String::from("test")), status: CIResultStatus::Failure },
+            ];
+        }
+
+        assert_eq!(next(None, status), format!("🙁 CI build has FAILED: {URL}\n\n- build: passed\n- test: failed"));
+    }
+
+    #[test]
+    fn will_render_repository_templates() {
+        let templates = ReportTemplates {
+            queued: Some(String::from("CI {{status}}")),
+            starting: Some(String::from("Build started, follow it at {{url}}")),
+            passed: Some(String::from("CI {{status}} in {{duration}}\n{{url}}")),
+            ..Default::default()
+        };
+        let running = StatusComment::next(None, RevisionStatus::Running { url: String::from(URL) }).render(&templates);
+        let passed = StatusComment::next(Some(&running), finished(CIResultStatus::Success, URL)).render(&templates);
+        let requeued = StatusComment::next(Some(&passed), RevisionStatus::Queued);
+
+        assert_eq!(running, format!("Build started, follow it at {URL}"));
+        assert_eq!(passed, format!("CI PASSED in 1m 23s\n{URL}"));
+        assert_eq!(requeued.history, vec![String::from("CI PASSED in 1m 23s")]);
+        assert_eq!(requeued.render(&templates), "CI QUEUED\n\nPrevious builds:\n- CI PASSED in 1m 23s");
+    }
+}
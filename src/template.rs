@@ -0,0 +1,58 @@
+/// Replaces every `{{name}}` placeholder of a template with its value. Whitespace around the name
+/// is ignored, placeholders without a value are left as they are.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        let name = rest[start + 2..end].trim();
+
+        output.push_str(&rest[..start]);
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => output.push_str(value),
+            None => output.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Formats a duration in seconds the way it is shown in reports, e.g. `1h 2m 5s`.
+pub fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m {seconds}s"),
+        _ => format!("{hours}h {minutes}m {seconds}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::{format_duration, render};
+
+    #[test]
+    fn will_replace_placeholders() {
+        let rendered = render("Build {{status}}: {{ url }}", &[("status", "PASSED"), ("url", "http://ci/builds/1")]);
+
+        assert_eq!(rendered, "Build PASSED: http://ci/builds/1");
+    }
+
+    #[test]
+    fn will_keep_unknown_and_unterminated_placeholders() {
+        assert_eq!(render("{{status}} {{unknown}} {{url", &[("status", "FAILED")]), "FAILED {{unknown}} {{url");
+    }
+
+    #[test]
+    fn will_format_durations() {
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(format_duration(83), "1m 23s");
+        assert_eq!(format_duration(3725), "1h 2m 5s");
+    }
+}
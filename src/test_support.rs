@@ -0,0 +1,155 @@
+use std::convert::Infallible;
+use std::fs;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, HeaderMap, Request, Response, Server, StatusCode};
+
+use crate::ci::CIBuild;
+use crate::error::{Error, PatchError};
+use crate::report::{BuildSubject, Outcome, Reporter};
+
+/// A request received by a stand-in server.
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+pub type Requests = Arc<Mutex<Vec<Received>>>;
+
+/// Starts an HTTP server standing in for Concourse or a webhook receiver. It records every request
+/// and answers with the given statuses and bodies in turn, and with an empty 200 once they run out.
+/// Returns the URL it listens on.
+pub fn stand_in(responses: Vec<(u16, &'static str)>) -> (String, Requests) {
+    let received = Requests::default();
+    let responses = Arc::new(Mutex::new(responses));
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    let requests = received.clone();
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let service = make_service_fn(move |_| {
+                let requests = requests.clone();
+                let responses = responses.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                        let requests = requests.clone();
+                        let responses = responses.clone();
+                        async move {
+                            let (parts, body) = request.into_parts();
+                            let body = hyper::body::to_bytes(body).await.unwrap();
+                            requests.lock().unwrap().push(Received {
+                                method: parts.method.to_string(),
+                                path: parts.uri.path().to_string(),
+                                headers: parts.headers,
+                                body: String::from_utf8_lossy(&body).into_owned(),
+                            });
+
+                            let mut responses = responses.lock().unwrap();
+                            let (status, body) = if responses.is_empty() { (200, "") } else { responses.remove(0) };
+                            Ok::<_, Infallible>(Response::builder().status(StatusCode::from_u16(status).unwrap()).body(Body::from(body)).unwrap())
+                        }
+                    }))
+                }
+            });
+            let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(service);
+            sender.send(server.local_addr()).unwrap();
+            server.await.unwrap();
+        });
+    });
+
+    (format!("http://{}", receiver.recv().unwrap()), received)
+}
+
+/// A path in the temporary directory, unique to the test process, that is removed when dropped.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("radicle-ci-{name}-{}", std::process::id()));
+        remove(&path);
+        Self(path)
+    }
+
+    /// Creates a file with the given content at the path.
+    pub fn with_content(name: &str, content: &str) -> Self {
+        let path = Self::new(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        remove(&self.0);
+    }
+}
+
+/// A reporter that records what it was told.
+#[derive(Default)]
+pub struct Recorder {
+    events: Mutex<Vec<String>>,
+    fail: bool,
+}
+
+impl Recorder {
+    /// A recorder that fails every report after recording it.
+    pub fn failing() -> Self {
+        Self { fail: true, ..Default::default() }
+    }
+
+    pub fn events(&self) -> Vec<String> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn record(&self, event: String) -> Result<(), Error> {
+        self.events.lock().unwrap().push(event);
+        if self.fail {
+            return Err(PatchError::NotFound(String::from("f0b0c8a4d3e2")).into());
+        }
+        Ok(())
+    }
+}
+
+impl Reporter for Recorder {
+    fn on_queued(&self, subject: &BuildSubject) -> Result<(), Error> {
+        self.record(format!("queued {}", subject.head))
+    }
+
+    fn on_started(&self, _subject: &BuildSubject, build: &CIBuild) -> Result<(), Error> {
+        self.record(format!("started #{}", build.id))
+    }
+
+    fn on_finished(&self, _subject: &BuildSubject, outcome: &Outcome) -> Result<(), Error> {
+        match outcome {
+            Outcome::Completed(result) => self.record(format!("finished {:?}", result.status)),
+            Outcome::Conflicts(paths) => self.record(format!("conflicts {}", paths.join(","))),
+        }
+    }
+}
+
+fn remove(path: &Path) {
+    let _ = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+}
@@ -1,27 +1,89 @@
-use anyhow::anyhow;
+use std::sync::Arc;
+
 use crossbeam_channel::{Receiver, RecvError};
 use git2::{Oid, Repository};
-use radicle::cob::patch::Patches;
-use radicle::prelude::{Id, ReadStorage};
+use radicle::cob::patch::{Patch, Patches, RevisionId, State};
+use radicle::cob::ObjectId;
+use radicle::cob::thread::CommentId;
+use radicle::crypto::{PublicKey, Signer};
+use radicle::identity::Did;
+use radicle::prelude::{Id, ReadRepository, ReadStorage};
+use radicle::storage::git::Repository as StorageRepository;
+use radicle::storage::WriteRepository;
 use radicle::Profile;
-use radicle_term as term;
+use serde::Serialize;
+use tracing::{debug, error, field, info, info_span, warn, Span};
+
+use crate::builds::{Cancellation, RevisionKey, RunningBuilds};
+use crate::ci::{CI, CIBuild, CIJob, CIResult, JobName, PipelineConfig};
+use crate::cob::JobRuns;
+use crate::command::CICommand;
+use crate::config::BrokerConfig;
+use crate::error::{ConfigError, Error, PatchError, StorageError};
+use crate::merge::{merge_onto, merge_ref, remove_merge_ref, MergeOutcome};
+use crate::metrics::{metrics, SkipReason};
+use crate::monitor::Monitor;
+use crate::report::{BuildSubject, Outcome, Reporters};
+use crate::results::{BuildResult, BuildResults};
+use crate::schedule::unix_now;
+
+/// The reference update a job was enqueued for.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// A patch was updated by the operation `entry`, which is also the id of the revision it
+    /// created, if it created one.
+    Patch { patch_id: String, entry: String },
+    /// A branch of the `remote` namespace was updated to `head`.
+    Push { remote: String, branch: String, head: String },
+    /// A tag of the `remote` namespace was created or moved to `head`.
+    Tag { remote: String, tag: String, head: String },
+    /// A scheduled build of the default branch is due.
+    Scheduled,
+}
 
-use crate::ci::{CI, CIJob, PipelineConfig};
+impl Trigger {
+    pub fn patch_id(&self) -> Option<&str> {
+        match self {
+            Trigger::Patch { patch_id, .. } => Some(patch_id),
+            _ => None,
+        }
+    }
+}
 
 pub struct WorkerContext {
-    patch_id: String,
+    trigger: Trigger,
     profile: Profile,
     rid: Id,
 }
 
+/// State shared between all workers.
+#[derive(Clone, Default)]
+pub struct WorkerState {
+    pub running: RunningBuilds,
+    pub config: Arc<BrokerConfig>,
+    pub results: BuildResults,
+    pub reporters: Reporters,
+    pub monitor: Monitor,
+}
+
+/// The pipeline configuration used for patch and branch builds.
+const PIPELINE_CONFIG_PATH: &str = ".concourse/config.yaml";
+
+fn parse_oid(oid: &str) -> Result<Oid, Error> {
+    Oid::from_str(oid).map_err(|error| StorageError::new(format!("Invalid object id {oid}"), error).into())
+}
+
 fn load_pipeline_configuration_from_commit(
     working: &Repository,
     commit_oid: Oid,
-) -> anyhow::Result<PipelineConfig> {
-    let commit = working.find_commit(commit_oid)?;
+    path: &str,
+) -> Result<PipelineConfig, Error> {
+    let commit = working.find_commit(commit_oid)
+        .map_err(|error| StorageError::new(format!("Failed to find commit {commit_oid}"), error))?;
 
-    let tree = commit.tree()?;
-    let path = ".concourse/config.yaml";
+    let tree = commit.tree()
+        .map_err(|error| StorageError::new(format!("Failed to read tree of commit {commit_oid}"), error))?;
 
     if let Ok(entry) = tree.get_path(path.as_ref()) {
         if let Ok(blob) = entry.to_object(working) {
@@ -32,72 +94,730 @@ fn load_pipeline_configuration_from_commit(
         }
     }
 
-    Err(anyhow!("File {} not found in commit {:?}", path, commit_oid))
+    Err(ConfigError::PipelineConfigNotFound { path: path.to_string(), commit: commit_oid.to_string() }.into())
 }
 
 
 impl WorkerContext {
-    pub fn new(rid: Id, patch_id: String, profile: Profile) -> Self {
-        Self { rid, patch_id, profile }
+    pub fn new(rid: Id, patch_id: String, entry: String, profile: Profile) -> Self {
+        Self { rid, trigger: Trigger::Patch { patch_id, entry }, profile }
+    }
+
+    pub fn push(rid: Id, remote: String, branch: String, head: String, profile: Profile) -> Self {
+        Self { rid, trigger: Trigger::Push { remote, branch, head }, profile }
+    }
+
+    pub fn tag(rid: Id, remote: String, tag: String, head: String, profile: Profile) -> Self {
+        Self { rid, trigger: Trigger::Tag { remote, tag, head }, profile }
+    }
+
+    pub fn scheduled(rid: Id, profile: Profile) -> Self {
+        Self { rid, trigger: Trigger::Scheduled, profile }
+    }
+
+    pub fn rid(&self) -> &Id {
+        &self.rid
     }
+
+    pub fn trigger(&self) -> &Trigger {
+        &self.trigger
+    }
+}
+
+/// What the worker has to do for a patch that was updated.
+enum Action {
+    /// Build a revision, either the whole pipeline or a single job of it. Builds requested through a
+    /// comment command are acknowledged with a reply.
+    Build { revision_id: RevisionId, head: String, job_name: Option<JobName>, requested_by: Option<CommentId> },
+    Cancel { revision_id: RevisionId, requested_by: CommentId },
+    /// Reply to a command comment that cannot be acted on.
+    Reject { revision_id: RevisionId, requested_by: CommentId, reason: String },
+}
+
+impl Action {
+    /// The comment the action was requested with, if it was requested through a command.
+    fn requested_by(&self) -> Option<CommentId> {
+        match self {
+            Action::Build { requested_by, .. } => *requested_by,
+            Action::Cancel { requested_by, .. } | Action::Reject { requested_by, .. } => Some(*requested_by),
+        }
+    }
+}
+
+/// How starting the build of a patch revision went.
+enum Started {
+    Triggered { ci_job: CIJob, build: CIBuild },
+    /// The revision does not merge cleanly into the default branch, with the conflicting paths.
+    Conflicts(Vec<String>),
+}
+
+/// Returns true if the broker has recorded a job run of the revision, i.e. it has been built before.
+fn has_been_built(repository: &StorageRepository, revision_id: &str, broker: &PublicKey) -> Result<bool, Error> {
+    JobRuns::open(repository)
+        .and_then(|runs| runs.has_run(revision_id, broker))
+        .map_err(|error| StorageError::new(format!("Failed to load job runs of repository {}", repository.id), error).into())
+}
+
+/// Collects the commands given in revision comments that the broker has not replied to yet.
+fn pending_commands(patch: &Patch, broker: &PublicKey, is_authorized: impl Fn(&PublicKey) -> bool) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    for (revision_id, revision) in patch.revisions() {
+        let comments = revision.discussion().comments().collect::<Vec<_>>();
+        let acknowledged = comments.iter()
+            .filter(|(_, comment)| comment.author() == *broker)
+            .filter_map(|(_, comment)| comment.reply_to())
+            .collect::<Vec<_>>();
+
+        for (comment_id, comment) in comments {
+            if comment.author() == *broker || acknowledged.contains(comment_id) {
+                continue;
+            }
+            let Some(command) = CICommand::parse(comment.body()) else {
+                continue;
+            };
+            let requested_by = *comment_id;
+
+            let action = if !is_authorized(&comment.author()) {
+                Action::Reject {
+                    revision_id,
+                    requested_by,
+                    reason: format!("{} is not authorized to run `{command}`.", Did::from(comment.author())),
+                }
+            } else {
+                match command {
+                    CICommand::Rerun => Action::Build { revision_id, head: revision.head().to_string(), job_name: None, requested_by: Some(requested_by) },
+                    CICommand::Run(job_name) => Action::Build { revision_id, head: revision.head().to_string(), job_name: Some(job_name), requested_by: Some(requested_by) },
+                    CICommand::Cancel => Action::Cancel { revision_id, requested_by },
+                    CICommand::Unknown(_) => Action::Reject { revision_id, requested_by, reason: format!("Unknown CI command `{command}`.") },
+                }
+            };
+            actions.push(action);
+        }
+    }
+
+    actions
 }
 
 pub struct Worker<T: CI + Send> {
     pub(crate) id: usize,
     receiver: Receiver<WorkerContext>,
     ci: T,
+    running: RunningBuilds,
+    config: Arc<BrokerConfig>,
+    results: BuildResults,
+    reporters: Reporters,
+    monitor: Monitor,
 }
 
 impl<T: CI + Send> Worker<T> {
-    pub fn new(id: usize, receiver: Receiver<WorkerContext>, ci: T) -> Self {
-        Self { id, receiver, ci }
+    pub fn new(id: usize, receiver: Receiver<WorkerContext>, ci: T, WorkerState { running, config, results, reporters, monitor }: WorkerState) -> Self {
+        Self { id, receiver, ci, running, config, results, reporters, monitor }
     }
 
     pub fn run(&mut self) -> Result<(), RecvError> {
         loop {
             let job = self.receiver.recv()?;
-            self.process(job);
+            let span = info_span!("job", worker = self.id, rid = %job.rid(), patch = job.trigger().patch_id());
+            let _entered = span.enter();
+            self.monitor.started(self.id, job.rid(), job.trigger());
+            let result = self.process(job);
+            if let Err(error) = &result {
+                error!(category = error.category(), %error, "CI job failed");
+            }
+            self.monitor.finished(self.id, result.err().as_ref());
+        }
+    }
+
+    /// Registers the build of a patch revision as running, so that it can be cancelled while its
+    /// pipeline is being set up. Returns false if the revision is already being built or, unless it
+    /// is built again on request, if the broker built it before. Builds are registered before that
+    /// is checked, so that two jobs of a patch never build a revision both.
+    fn claim_build(&self, repository: &StorageRepository, broker: &PublicKey, key: &RevisionKey, rebuild: bool) -> Result<bool, Error> {
+        if !self.running.start(key.clone()) {
+            info!(revision = %key.revision_id, "Revision is already being built");
+            return Ok(false);
+        }
+        if rebuild {
+            return Ok(true);
+        }
+
+        match has_been_built(repository, &key.revision_id, broker) {
+            Ok(false) => Ok(true),
+            built => {
+                self.running.finish(key);
+                if let Ok(true) = built {
+                    info!(revision = %key.revision_id, "Revision has already been built");
+                }
+                built.map(|_| false)
+            }
+        }
+    }
+
+    /// Sets up and triggers the build of a patch revision, which has been registered as running
+    /// under `key`. The revision is merged onto the default branch first for merge builds.
+    fn start_revision_build<G: Signer>(
+        &mut self,
+        repository: &StorageRepository,
+        signer: &G,
+        subject: &BuildSubject,
+        key: &RevisionKey,
+        merge: bool,
+        job_name: Option<&JobName>,
+    ) -> Result<Started, Error> {
+        let head = if merge {
+            match self.merge_revision(repository, signer, &key.patch_id, &subject.head)? {
+                MergeOutcome::Merged(commit) => commit.to_string(),
+                MergeOutcome::Conflicts(paths) => return Ok(Started::Conflicts(paths)),
+            }
+        } else {
+            subject.head.clone()
+        };
+
+        debug!(%head, "Loading Concourse configuration file");
+        let pipeline_config = load_pipeline_configuration_from_commit(&repository.backend, parse_oid(&head)?, PIPELINE_CONFIG_PATH)?;
+
+        let ci_job = CIJob {
+            patch_revision_id: Some(key.revision_id.clone()),
+            patch_head: head,
+            project_id: key.rid.clone(),
+            pipeline_config,
+            patch_id: Some(key.patch_id.clone()),
+            branch: None,
+            tag_name: None,
+        };
+
+        info!(job = ?job_name, "Building revision");
+        let pipeline_name = self.ci.setup(&ci_job)?;
+        let build = match job_name {
+            Some(job_name) => self.ci.trigger_pipeline_job(&ci_job, &pipeline_name, job_name)?,
+            None => self.ci.trigger_pipeline(&ci_job, &pipeline_name)?,
+        };
+        if self.running.triggered(key, build.clone()) {
+            info!(build = build.id, "Build was cancelled while it was being triggered");
+            self.ci.abort_build(&build)?;
+        }
+
+        Ok(Started::Triggered { ci_job, build })
+    }
+
+    fn process(&mut self, WorkerContext { trigger, rid, profile }: WorkerContext) -> Result<(), Error> {
+        match trigger {
+            Trigger::Patch { patch_id, entry } => self.process_patch(rid, patch_id, entry, profile),
+            Trigger::Push { remote, branch, head } => self.process_push(rid, remote, branch, head, profile),
+            Trigger::Tag { remote, tag, head } => self.process_tag(rid, remote, tag, head, profile),
+            Trigger::Scheduled => self.process_scheduled(rid, profile),
         }
     }
 
-    fn process(&mut self, WorkerContext { patch_id, rid, profile }: WorkerContext) {
-        let repository = profile.storage.repository(rid).unwrap();
-        let mut patches = Patches::open(&repository).unwrap();
-        let mut patch = patches.get_mut(&patch_id.parse().unwrap()).unwrap();
+    fn process_push(&mut self, rid: Id, remote: String, branch: String, head: String, profile: Profile) -> Result<(), Error> {
+        let repository = profile.storage.repository(rid)
+            .map_err(|error| StorageError::new(format!("Failed to open repository {rid}"), error))?;
         let repository_id = repository.id.canonical();
-        let (revision_id, _) = patch.revisions().last().unwrap();
 
-        term::info!("[{}] Loading concourse configuration file", self.id);
-        let pipeline_config = load_pipeline_configuration_from_commit(&repository.backend, **patch.head()).unwrap();
+        let Some(config) = self.config.repository(&repository_id) else {
+            metrics().job_skipped(SkipReason::NotNeeded);
+            return Ok(());
+        };
+        let project = repository.project()
+            .map_err(|error| StorageError::new(format!("Failed to load project of repository {rid}"), error))?;
+        if !config.builds_branch(&branch, project.default_branch().as_str()) || !self.is_delegate(&repository, &remote)? {
+            metrics().job_skipped(SkipReason::NotNeeded);
+            return Ok(());
+        }
+        let signer = profile.signer()
+            .map_err(|error| StorageError::new("Failed to load node signer", error))?;
+
+        self.build_branch(&repository, &signer, branch, head)
+    }
+
+    /// Builds the canonical head of the default branch, i.e. the one the delegates agree on.
+    fn process_scheduled(&mut self, rid: Id, profile: Profile) -> Result<(), Error> {
+        let repository = profile.storage.repository(rid)
+            .map_err(|error| StorageError::new(format!("Failed to open repository {rid}"), error))?;
+        let project = repository.project()
+            .map_err(|error| StorageError::new(format!("Failed to load project of repository {rid}"), error))?;
+        let (_, head) = repository.canonical_head()
+            .map_err(|error| StorageError::new(format!("Failed to find canonical head of repository {rid}"), error))?;
+
+        let signer = profile.signer()
+            .map_err(|error| StorageError::new("Failed to load node signer", error))?;
+
+        info!("Running scheduled build");
+        self.build_branch(&repository, &signer, project.default_branch().to_string(), head.to_string())
+    }
+
+    /// Builds a head of a branch and records its result under the branch name.
+    fn build_branch<G: Signer>(&mut self, repository: &StorageRepository, signer: &G, branch: String, head: String) -> Result<(), Error> {
+        let repository_id = repository.id.canonical();
+        let project = repository.project()
+            .map_err(|error| StorageError::new(format!("Failed to load project of repository {}", repository.id), error))?;
+
+        debug!(%head, "Loading Concourse configuration file");
+        let pipeline_config = load_pipeline_configuration_from_commit(&repository.backend, parse_oid(&head)?, PIPELINE_CONFIG_PATH)?;
 
         let ci_job = CIJob {
-            patch_revision_id: revision_id.clone().to_string(),
-            patch_head: patch.head().to_string(),
+            patch_revision_id: None,
+            patch_head: head.clone(),
             project_id: repository_id.clone(),
             pipeline_config,
+            patch_id: None,
+            branch: Some(branch.clone()),
+            tag_name: None,
         };
 
+        info!(%branch, "Building branch");
+        let ci_result = self.run_recorded(repository, signer, &ci_job)?;
 
-        let signer = profile.signer().unwrap();
-        let (revision_id, _) = patch.revisions().last().unwrap();
-        patch.comment(revision_id, "New CI build is starting", None, &signer)
-            .map_or_else(
-                |error| term::info!("[{}] Unable to create a patch comment {:?}", self.id, error),
-                |_| term::info!("[{}] New CI build patch comment created", self.id),
-            );
+        info!(%branch, status = ?ci_result.status, url = %ci_result.url, "Branch build finished");
+        let result = BuildResult::new(head, ci_result);
+        if project.default_branch().as_str() == branch {
+            self.results.record_default_branch(&repository_id, &branch, result)?;
+        } else {
+            self.results.record_branch(&repository_id, &branch, result)?;
+        }
 
-        term::info!("[{}] Worker received job {:#?}", self.id, ci_job);
+        Ok(())
+    }
+
+    fn process_tag(&mut self, rid: Id, remote: String, tag: String, head: String, profile: Profile) -> Result<(), Error> {
+        let repository = profile.storage.repository(rid)
+            .map_err(|error| StorageError::new(format!("Failed to open repository {rid}"), error))?;
+        let repository_id = repository.id.canonical();
+
+        let Some(config_path) = self.config.repository(&repository_id).and_then(|config| config.release_pipeline_config.clone()) else {
+            metrics().job_skipped(SkipReason::NotNeeded);
+            return Ok(());
+        };
+        if !self.is_delegate(&repository, &remote)? {
+            metrics().job_skipped(SkipReason::NotNeeded);
+            return Ok(());
+        }
+        let signer = profile.signer()
+            .map_err(|error| StorageError::new("Failed to load node signer", error))?;
+
+        // Annotated tags point to a tag object rather than the commit to build.
+        let commit = repository.backend.find_object(parse_oid(&head)?, None)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|error| StorageError::new(format!("Failed to find commit of tag {tag}"), error))?;
+
+        debug!(path = %config_path, "Loading Concourse release configuration file");
+        let pipeline_config = load_pipeline_configuration_from_commit(&repository.backend, commit.id(), &config_path)?;
+
+        let ci_job = CIJob {
+            patch_revision_id: None,
+            patch_head: commit.id().to_string(),
+            project_id: repository_id.clone(),
+            pipeline_config,
+            patch_id: None,
+            branch: None,
+            tag_name: Some(tag.clone()),
+        };
+
+        info!(%tag, "Building release");
+        let ci_result = self.run_recorded(&repository, &signer, &ci_job)?;
+
+        info!(%tag, status = ?ci_result.status, url = %ci_result.url, "Release build finished");
+        self.results.record_release(&repository_id, &tag, BuildResult::new(commit.id().to_string(), ci_result))?;
+
+        Ok(())
+    }
+
+    /// Runs the pipeline of a branch or release build and records it as a job run.
+    fn run_recorded<G: Signer>(&mut self, repository: &StorageRepository, signer: &G, ci_job: &CIJob) -> Result<CIResult, Error> {
+        let subject = BuildSubject { rid: repository.id, patch_id: None, revision_id: None, head: ci_job.patch_head.clone() };
+        let span = info_span!("build", head = %ci_job.patch_head, build = field::Empty);
+        let _entered = span.enter();
+        self.reporters.on_queued(&subject);
+
+        let build = self.trigger(&subject, ci_job)?;
+        self.watch(repository, signer, &subject, ci_job, &build)
+    }
+
+    /// Sets up and triggers the pipeline of a queued branch or release build. The build is reported
+    /// as errored if that fails.
+    fn trigger(&mut self, subject: &BuildSubject, ci_job: &CIJob) -> Result<CIBuild, Error> {
         self.ci.setup(ci_job)
-            .and_then(|pipeline_name| self.ci.run_pipeline(&pipeline_name))
-            .map(|ci_result| {
-                let signer = profile.signer().unwrap();
-                let (revision_id, _) = patch.revisions().last().unwrap();
-                term::info!("[{}] Pipeline result: {}", self.id, ci_result.get_report_message());
-                patch.comment(revision_id, ci_result.get_report_message(), None, &signer)
-            })
-            .map_or_else(
-                |error| term::info!("[{}] CI pipeline job encountered an error: {:?}", self.id, error),
-                |_| term::info!("[{}] CI pipeline job completed and revision comment added to patch", self.id),
-            );
-    }
-}
\ No newline at end of file
+            .and_then(|pipeline_name| self.ci.trigger_pipeline(ci_job, &pipeline_name))
+            .map_err(|error| self.errored(subject, None, error))
+    }
+
+    /// Watches a triggered build until it completes, recording it as a job run and reporting it.
+    fn watch<G: Signer>(&mut self, repository: &StorageRepository, signer: &G, subject: &BuildSubject, ci_job: &CIJob, build: &CIBuild) -> Result<CIResult, Error> {
+        let run = self.record_run(repository, signer, ci_job, build);
+        let completed = self.complete(subject, build);
+        // The run would otherwise be left running forever when the build could not be watched.
+        let ci_result = match &completed {
+            Ok(ci_result) => ci_result.clone(),
+            Err(_) => CIResult::errored(build.url.clone()),
+        };
+        self.record_run_result(repository, signer, run.as_ref().ok().cloned(), &ci_result);
+
+        // The build is still watched and reported when it could not be recorded, but the job fails
+        // as the revision is built again on its next update.
+        completed.and_then(|ci_result| run.map(|_| ci_result))
+    }
+
+    /// Watches a triggered build until it completes and reports its result. The build is reported
+    /// as errored if it could not be watched.
+    fn complete(&mut self, subject: &BuildSubject, build: &CIBuild) -> Result<CIResult, Error> {
+        Span::current().record("build", build.id);
+        self.monitor.build_started(self.id, build);
+        self.reporters.on_started(subject, build);
+
+        let ci_result = self.ci.watch_build(build).map_err(|error| self.errored(subject, Some(build), error))?;
+        self.finish_build(subject, &Outcome::Completed(ci_result.clone()));
+
+        Ok(ci_result)
+    }
+
+    /// Counts the outcome of a build in the metrics and reports it.
+    fn finish_build(&self, subject: &BuildSubject, outcome: &Outcome) {
+        let duration = match outcome {
+            Outcome::Completed(result) => result.duration,
+            Outcome::Conflicts(_) => None,
+        };
+        metrics().build_finished(&subject.rid.canonical(), outcome.status(), duration);
+        self.reporters.on_finished(subject, outcome);
+    }
+
+    /// Reports a queued build that failed as errored, linking to it if it was triggered, and passes
+    /// the error on.
+    fn errored(&self, subject: &BuildSubject, build: Option<&CIBuild>, error: Error) -> Error {
+        let url = build.map(|build| build.url.clone()).unwrap_or_default();
+        self.finish_build(subject, &Outcome::Completed(CIResult::errored(url)));
+
+        error
+    }
+
+    /// Records a triggered build as a job run, which is what keeps a revision from being built again.
+    fn record_run<G: Signer>(&self, repository: &StorageRepository, signer: &G, ci_job: &CIJob, build: &CIBuild) -> Result<ObjectId, Error> {
+        JobRuns::open(repository)
+            .and_then(|mut runs| runs.create(build.id, ci_job.patch_revision_id.clone(), ci_job.patch_head.clone(), build.url.clone(), unix_now(), signer))
+            .map_err(|error| StorageError::new(format!("Failed to record job run of build {}", build.id), error).into())
+    }
+
+    fn record_run_result<G: Signer>(&self, repository: &StorageRepository, signer: &G, run: Option<ObjectId>, ci_result: &CIResult) {
+        let Some(id) = run else {
+            return;
+        };
+        if let Err(error) = JobRuns::open(repository).and_then(|mut runs| runs.finish(&id, ci_result, unix_now(), signer)) {
+            warn!(run = %id, %error, "Failed to record result of job run");
+        }
+    }
+
+    /// Merges a revision head onto the canonical head of the default branch and publishes the merge
+    /// commit in the broker's namespace, where the pipeline can fetch it from.
+    fn merge_revision<G: Signer>(&self, repository: &StorageRepository, signer: &G, patch_id: &str, head: &str) -> Result<MergeOutcome, Error> {
+        let (_, base) = repository.canonical_head()
+            .map_err(|error| StorageError::new(format!("Failed to find canonical head of repository {}", repository.id), error))?;
+        let outcome = merge_onto(&repository.backend, *base, parse_oid(head)?)
+            .map_err(|error| StorageError::new(format!("Failed to merge {head} onto {base}"), error))?;
+
+        if let MergeOutcome::Merged(commit) = &outcome {
+            info!(%head, %base, %commit, "Merged revision onto the default branch");
+            let name = merge_ref(&signer.public_key().to_string(), patch_id);
+            repository.backend.reference(&name, *commit, true, "radicle-ci: merge result")
+                .map_err(|error| StorageError::new(format!("Failed to update {name}"), error))?;
+            repository.sign_refs(signer)
+                .map_err(|error| StorageError::new("Failed to sign references", error))?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Aborts the running builds of a patch that was archived or merged, destroys its pipeline and
+    /// deletes the reference to its merge commit.
+    fn clean_up_patch<G: Signer>(&mut self, repository: &StorageRepository, signer: &G, patch_id: &str) -> Result<(), Error> {
+        let repository_id = repository.id.canonical();
+        for key in self.running.of_patch(&repository_id, patch_id) {
+            if let Cancellation::Abort(build) = self.running.cancel(&key) {
+                info!(build = build.id, revision = %key.revision_id, "Aborting build of closed patch");
+                self.ci.abort_build(&build)?;
+            }
+        }
+
+        self.ci.destroy_patch_pipeline(&repository_id, patch_id)?;
+
+        let removed = remove_merge_ref(&repository.backend, &signer.public_key().to_string(), patch_id)
+            .map_err(|error| StorageError::new(format!("Failed to delete merge reference of patch {patch_id}"), error))?;
+        if removed {
+            info!("Deleted merge reference of closed patch");
+            repository.sign_refs(signer)
+                .map_err(|error| StorageError::new("Failed to sign references", error))?;
+        }
+
+        Ok(())
+    }
+
+    /// Only delegates can trigger branch and release builds, otherwise anyone could run pipelines by
+    /// pushing to their own namespace.
+    fn is_delegate(&self, repository: &StorageRepository, remote: &str) -> Result<bool, Error> {
+        let delegates = repository.delegates()
+            .map_err(|error| StorageError::new(format!("Failed to load delegates of repository {}", repository.id), error))?;
+        let is_delegate = delegates.iter().any(|delegate| (**delegate).to_string() == remote);
+        if !is_delegate {
+            info!(%remote, "Ignoring reference update by a user who is not a delegate");
+        }
+
+        Ok(is_delegate)
+    }
+
+    fn process_patch(&mut self, rid: Id, patch_id: String, entry: String, profile: Profile) -> Result<(), Error> {
+        let repository = profile.storage.repository(rid)
+            .map_err(|error| StorageError::new(format!("Failed to open repository {rid}"), error))?;
+        let mut patches = Patches::open(&repository)
+            .map_err(|error| StorageError::new(format!("Failed to open patches of repository {rid}"), error))?;
+        let id = patch_id.parse().map_err(|_| PatchError::InvalidId(patch_id.clone()))?;
+        let mut patch = patches.get_mut(&id).map_err(|_| PatchError::NotFound(patch_id.clone()))?;
+        let repository_id = repository.id.canonical();
+        let repository_config = self.config.repository(&repository_id).cloned().unwrap_or_default();
+
+        let signer = profile.signer()
+            .map_err(|error| StorageError::new("Failed to load node signer", error))?;
+
+        match patch.state() {
+            State::Archived | State::Merged { .. } => return self.clean_up_patch(&repository, &signer, &patch_id),
+            State::Draft if !repository_config.build_drafts => {
+                info!("Skipping draft patch");
+                metrics().job_skipped(SkipReason::NotNeeded);
+                return Ok(());
+            }
+            _ => (),
+        }
+
+        let broker = profile.public_key;
+        let revision_key = |revision_id: &RevisionId| RevisionKey {
+            rid: repository_id.clone(),
+            patch_id: patch_id.clone(),
+            revision_id: revision_id.to_string(),
+        };
+
+        let delegates = repository.delegates()
+            .map_err(|error| StorageError::new(format!("Failed to load delegates of repository {rid}"), error))?;
+        let is_authorized = |author: &PublicKey| {
+            delegates.iter().any(|delegate| **delegate == *author)
+                || repository_config.ci_command_users.contains(&Did::from(*author).to_string())
+        };
+
+        let mut actions = Vec::new();
+        // Build exactly the revision the update created. By the time the job is processed a newer
+        // revision may have arrived, which is built when its own update is processed. Other updates,
+        // e.g. a draft being marked as ready for review, build the latest revision if it was skipped.
+        let revision = match patch.revisions().find(|(revision_id, _)| revision_id.to_string() == entry) {
+            Some(revision) => Some(revision),
+            None => {
+                debug!(%entry, "Patch update did not create a revision, checking the latest revision");
+                patch.revisions().last()
+            }
+        };
+        match revision {
+            Some((revision_id, revision)) => {
+                actions.push(Action::Build { revision_id, head: revision.head().to_string(), job_name: None, requested_by: None });
+            }
+            None => {
+                debug!(%entry, "Patch has no revision to build");
+                metrics().job_skipped(SkipReason::NotNeeded);
+            }
+        }
+        actions.extend(pending_commands(&patch, &broker, is_authorized));
+
+        // A failing action does not keep the others from being processed, nor its command from
+        // being answered. The job fails with the first failure once every action was processed.
+        let mut failure = None;
+        let mut reply = |revision_id: RevisionId, requested_by: CommentId, body: String| -> Result<(), Error> {
+            patch.comment(revision_id, body, Some(requested_by), &signer)
+                .map(|_| ())
+                .map_err(|error| PatchError::Comment { patch: patch_id.clone(), source: error.into() }.into())
+        };
+        for action in actions {
+            // The command is taken on before anything slow is done, so that no other job of the
+            // patch acts on it too while it has not been answered yet.
+            if let Some(requested_by) = action.requested_by() {
+                if !self.running.claim_command(&requested_by.to_string()) {
+                    debug!(comment = %requested_by, "CI command is handled by another job");
+                    continue;
+                }
+            }
+            let result: Result<(), Error> = match action {
+                Action::Build { revision_id, head, job_name, requested_by } => match self.claim_build(&repository, &broker, &revision_key(&revision_id), requested_by.is_some()) {
+                    Err(error) => Err(error),
+                    Ok(false) => match requested_by {
+                        Some(requested_by) => reply(revision_id, requested_by, String::from("A CI build of this revision is already running.")),
+                        None => {
+                            metrics().job_skipped(SkipReason::Deduped);
+                            Ok(())
+                        }
+                    },
+                    Ok(true) => {
+                        let subject = BuildSubject {
+                            rid,
+                            patch_id: Some(patch_id.clone()),
+                            revision_id: Some(revision_id.to_string()),
+                            head: head.clone(),
+                        };
+                        let span = info_span!("build", revision = %revision_id, head = %head, build = field::Empty);
+                        let _entered = span.enter();
+                        self.reporters.on_queued(&subject);
+
+                        let key = revision_key(&revision_id);
+                        let started = self.start_revision_build(&repository, &signer, &subject, &key, repository_config.merge_builds, job_name.as_ref())
+                            .map_err(|error| self.errored(&subject, self.running.get(&key).as_ref(), error));
+
+                        let replied = match requested_by {
+                            Some(requested_by) => {
+                                let body = match (&started, &job_name) {
+                                    (Ok(Started::Triggered { build, .. }), Some(job_name)) => format!("Running CI job {job_name}: {}", build.url),
+                                    (Ok(Started::Triggered { build, .. }), None) => format!("Re-running CI build: {}", build.url),
+                                    (Ok(Started::Conflicts(paths)), _) => format!("Unable to start CI build, the revision does not merge cleanly: {}", paths.join(", ")),
+                                    (Err(error), _) => format!("Unable to start CI build: {error}"),
+                                };
+                                reply(revision_id, requested_by, body)
+                            }
+                            None => Ok(()),
+                        };
+
+                        let result = match started {
+                            Ok(Started::Triggered { ci_job, build }) => self.watch(&repository, &signer, &subject, &ci_job, &build)
+                                .map(|ci_result| info!(status = ?ci_result.status, url = %ci_result.url, "Revision build finished")),
+                            Ok(Started::Conflicts(paths)) => {
+                                info!(?paths, "Revision does not merge cleanly");
+                                self.finish_build(&subject, &Outcome::Conflicts(paths));
+                                Ok(())
+                            }
+                            Err(error) => Err(error),
+                        };
+                        self.running.finish(&key);
+
+                        result.and(replied)
+                    }
+                },
+                Action::Cancel { revision_id, requested_by } => {
+                    let (body, aborted) = match self.running.cancel(&revision_key(&revision_id)) {
+                        Cancellation::Abort(build) => match self.ci.abort_build(&build) {
+                            Ok(()) => (format!("Cancelled CI build: {}", build.url), Ok(())),
+                            Err(error) => (format!("Unable to cancel CI build: {error}"), Err(error)),
+                        },
+                        Cancellation::Pending => (String::from("Cancelled CI build before it started."), Ok(())),
+                        Cancellation::NotRunning => (String::from("There is no running CI build for this revision."), Ok(())),
+                    };
+                    reply(revision_id, requested_by, body).and(aborted)
+                }
+                Action::Reject { revision_id, requested_by, reason } => {
+                    info!(%reason, "Rejecting CI command");
+                    reply(revision_id, requested_by, reason)
+                }
+            };
+
+            match (result, &failure) {
+                (Err(error), None) => failure = Some(error),
+                (Err(error), Some(_)) => error!(category = error.category(), %error, "CI action failed"),
+                (Ok(()), _) => (),
+            }
+        }
+
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::StatusCode;
+    use radicle::prelude::Id;
+
+    use crate::ci::{CI, CIBuild, CIJob, CIResult, JobName, PipelineConfig, PipelineName};
+    use crate::concourse::error::ConcourseError;
+    use crate::concourse::response_error::ResponseError;
+    use crate::error::Error;
+    use crate::report::{BuildSubject, Reporters};
+    use crate::test_support::Recorder;
+    use crate::worker::{Worker, WorkerState};
+
+    /// Stands in for a Concourse that fails every request.
+    #[derive(Clone)]
+    struct Unavailable;
+
+    fn unavailable() -> Error {
+        let error = ResponseError { errors: vec![String::from("service unavailable")], warnings: None };
+        Error::concourse("Failed to reach Concourse", ConcourseError::Response { status: StatusCode::SERVICE_UNAVAILABLE, error })
+    }
+
+    impl CI for Unavailable {
+        fn setup(&mut self, _job: &CIJob) -> Result<PipelineName, Error> {
+            Err(unavailable())
+        }
+
+        fn trigger_pipeline(&mut self, _job: &CIJob, _pipeline_name: &PipelineName) -> Result<CIBuild, Error> {
+            Err(unavailable())
+        }
+
+        fn trigger_pipeline_job(&mut self, _job: &CIJob, _pipeline_name: &PipelineName, _job_name: &JobName) -> Result<CIBuild, Error> {
+            Err(unavailable())
+        }
+
+        fn watch_build(&mut self, _build: &CIBuild) -> Result<CIResult, Error> {
+            Err(unavailable())
+        }
+
+        fn abort_build(&mut self, _build: &CIBuild) -> Result<(), Error> {
+            Err(unavailable())
+        }
+
+        fn destroy_patch_pipeline(&mut self, _project_id: &str, _patch_id: &str) -> Result<(), Error> {
+            Err(unavailable())
+        }
+    }
+
+    fn worker(recorder: Arc<Recorder>) -> Worker<Unavailable> {
+        let (_, receiver) = crossbeam_channel::unbounded();
+        let state = WorkerState { reporters: Reporters::new(vec![recorder]), ..Default::default() };
+
+        Worker::new(0, receiver, Unavailable, state)
+    }
+
+    fn subject() -> BuildSubject {
+        BuildSubject {
+            rid: Id::from_urn("rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5").unwrap(),
+            patch_id: None,
+            revision_id: None,
+            head: String::from("d4e5f6"),
+        }
+    }
+
+    #[test]
+    fn will_report_builds_that_fail_to_start_as_errored() {
+        let recorder = Arc::new(Recorder::default());
+        let mut worker = worker(recorder.clone());
+        let ci_job = CIJob {
+            patch_revision_id: None,
+            patch_head: String::from("d4e5f6"),
+            project_id: String::from("z3gqcJUoA1n9HaHKufZs5FCSGazv5"),
+            pipeline_config: PipelineConfig(String::from("jobs: []")),
+            patch_id: None,
+            branch: Some(String::from("master")),
+            tag_name: None,
+        };
+
+        let error = worker.trigger(&subject(), &ci_job).unwrap_err();
+
+        assert_eq!(error.category(), "api");
+        assert_eq!(recorder.events(), vec![String::from("finished Errored")]);
+    }
+
+    #[test]
+    fn will_report_builds_that_fail_to_complete_as_errored() {
+        let recorder = Arc::new(Recorder::default());
+        let mut worker = worker(recorder.clone());
+        let build = CIBuild { id: 3094, url: String::from("http://localhost:8080/builds/3094") };
+
+        let error = worker.complete(&subject(), &build).unwrap_err();
+
+        assert_eq!(error.category(), "api");
+        assert_eq!(recorder.events(), vec![String::from("started #3094"), String::from("finished Errored")]);
+    }
+}